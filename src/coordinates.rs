@@ -0,0 +1,194 @@
+use bevy::prelude::*;
+
+use crate::{
+    layout::{BoardLayout, ScaledText},
+    square_position,
+    theme::{is_dark_square, Theme, ThemeFonts, Themes},
+    BoardOrientation, StatusMessage,
+};
+
+const COORDINATE_FONT_SIZE: f32 = 12.0;
+/// Distance of a label from the edges of its square, relative to the square size.
+const COORDINATE_MARGIN: f32 = 0.06;
+
+/// Labels the files along the bottom edge and the ranks along the left edge, inside the
+/// corner of each square. L shows or hides them and `--no-coordinates` starts with them hidden.
+pub struct CoordinatesPlugin;
+
+impl Plugin for CoordinatesPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CoordinateSettings {
+            visible: !std::env::args().any(|arg| arg == "--no-coordinates"),
+        })
+        .add_startup_system(spawn_coordinates)
+        .add_system(toggle_coordinates)
+        .add_system(place_coordinates.after(toggle_coordinates));
+    }
+}
+
+pub struct CoordinateSettings {
+    pub visible: bool,
+}
+
+/// A file or rank label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub enum Coordinate {
+    File(chess::File),
+    Rank(chess::Rank),
+}
+
+impl Coordinate {
+    /// The square the label is drawn in: the bottom rank for files, the left file for ranks.
+    fn square(self, orientation: BoardOrientation) -> chess::Square {
+        let bottom = orientation.bottom();
+        match self {
+            Self::File(file) => chess::Square::make_square(bottom.to_my_backrank(), file),
+            Self::Rank(rank) => {
+                let left = match bottom {
+                    chess::Color::White => chess::File::A,
+                    chess::Color::Black => chess::File::H,
+                };
+                chess::Square::make_square(rank, left)
+            }
+        }
+    }
+
+    fn label(self) -> String {
+        match self {
+            Self::File(file) => ((b'a' + file.to_index() as u8) as char).to_string(),
+            Self::Rank(rank) => (rank.to_index() + 1).to_string(),
+        }
+    }
+
+    /// The colour of the other kind of square, to stand out on its own.
+    fn color(self, orientation: BoardOrientation, theme: &Theme) -> Color {
+        if is_dark_square(self.square(orientation)) {
+            theme.light_square
+        } else {
+            theme.dark_square
+        }
+    }
+
+    /// Files sit in the bottom right corner, ranks in the top left one.
+    fn alignment(self) -> TextAlignment {
+        match self {
+            Self::File(_) => TextAlignment {
+                vertical: VerticalAlign::Bottom,
+                horizontal: HorizontalAlign::Right,
+            },
+            Self::Rank(_) => TextAlignment {
+                vertical: VerticalAlign::Top,
+                horizontal: HorizontalAlign::Left,
+            },
+        }
+    }
+
+    /// Where the aligned corner of the label goes.
+    fn position(self, orientation: BoardOrientation, piece_size: f32) -> Vec2 {
+        let center = square_position(self.square(orientation), orientation, piece_size);
+        let inset = piece_size * (0.5 - COORDINATE_MARGIN);
+        match self {
+            Self::File(_) => center + Vec2::new(inset, -inset),
+            Self::Rank(_) => center + Vec2::new(-inset, inset),
+        }
+    }
+}
+
+fn spawn_coordinates(
+    mut commands: Commands,
+    fonts: Res<ThemeFonts>,
+    themes: Res<Themes>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+) {
+    let font = fonts.coordinate_font.clone();
+    let files = chess::ALL_FILES.iter().map(|&file| Coordinate::File(file));
+    let ranks = chess::ALL_RANKS.iter().map(|&rank| Coordinate::Rank(rank));
+    for coordinate in files.chain(ranks) {
+        let style = TextStyle {
+            font: font.clone(),
+            font_size: COORDINATE_FONT_SIZE * layout.scale,
+            color: coordinate.color(*orientation, themes.current()),
+        };
+        let position = coordinate.position(*orientation, layout.piece_size());
+        // Above the squares and highlights, below the pieces
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(coordinate.label(), style)
+                    .with_alignment(coordinate.alignment()),
+                transform: Transform::from_translation(position.extend(2.5)),
+                ..default()
+            })
+            .insert(Name::new(format!("Coordinate {}", coordinate.label())))
+            .insert(ScaledText(COORDINATE_FONT_SIZE))
+            .insert(coordinate);
+    }
+}
+
+fn toggle_coordinates(
+    keys: Res<Input<KeyCode>>,
+    mut settings: ResMut<CoordinateSettings>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    if keys.just_pressed(KeyCode::L) {
+        settings.visible = !settings.visible;
+        let status = if settings.visible {
+            "Coordinates shown"
+        } else {
+            "Coordinates hidden"
+        };
+        status_evw.send(StatusMessage(status.to_string()));
+    }
+}
+
+/// Follows the orientation, size and theme of the board; `layout_board` scales the font and
+/// `apply_theme` swaps it.
+fn place_coordinates(
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    settings: Res<CoordinateSettings>,
+    themes: Res<Themes>,
+    mut coordinate_q: Query<(&Coordinate, &mut Text, &mut Transform, &mut Visibility)>,
+) {
+    if !layout.is_changed()
+        && !orientation.is_changed()
+        && !settings.is_changed()
+        && !themes.is_changed()
+    {
+        return;
+    }
+    for (coordinate, mut text, mut transform, mut visibility) in coordinate_q.iter_mut() {
+        let position = coordinate.position(*orientation, layout.piece_size());
+        transform.translation = position.extend(transform.translation.z);
+        text.sections[0].style.color = coordinate.color(*orientation, themes.current());
+        visibility.is_visible = settings.visible;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coordinate;
+    use crate::theme::Theme;
+    use crate::BoardOrientation;
+    use chess::{File, Rank, Square};
+
+    #[test]
+    fn labels_the_bottom_and_left_edges() {
+        let e = Coordinate::File(File::E);
+        let third = Coordinate::Rank(Rank::Third);
+        assert_eq!(e.label(), "e");
+        assert_eq!(third.label(), "3");
+
+        assert_eq!(e.square(BoardOrientation::White), Square::E1);
+        assert_eq!(third.square(BoardOrientation::White), Square::A3);
+        assert_eq!(e.square(BoardOrientation::Black), Square::E8);
+        assert_eq!(third.square(BoardOrientation::Black), Square::H3);
+
+        // a1 is a dark square and h1 a light one
+        let theme = Theme::default();
+        let a = Coordinate::File(File::A);
+        let h = Coordinate::File(File::H);
+        assert_eq!(a.color(BoardOrientation::White, &theme), theme.light_square);
+        assert_eq!(h.color(BoardOrientation::White, &theme), theme.dark_square);
+    }
+}
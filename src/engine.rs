@@ -0,0 +1,561 @@
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use chess::{Board, ChessMove, Color, MoveGen, Piece, ALL_PIECES};
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    arg_value, game::Game, replay::Replay, select_move, SelectingSquares, SquareComponent,
+    StatusMessage,
+};
+
+const MATE: i32 = 30_000;
+const INFINITY: i32 = 32_000;
+/// Scores beyond this are mates.
+const MATE_THRESHOLD: i32 = MATE - 1_000;
+/// Nodes searched between two looks at the clock.
+const NODES_PER_CLOCK_CHECK: u64 = 2_048;
+
+/// Lets the built-in engine play a side, chosen with `--engine white|black` or cycled with E.
+/// `--engine-depth` and `--engine-time <ms>` limit how long it thinks.
+pub struct EnginePlugin;
+
+impl Plugin for EnginePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(EngineSettings::from_args())
+            .add_system(cycle_engine_side)
+            .add_system(engine_move.after(cycle_engine_side));
+    }
+}
+
+pub struct EngineSettings {
+    /// The side the engine plays, if any.
+    pub color: Option<Color>,
+    pub limits: SearchLimits,
+    /// An external UCI engine plays the side instead of the built-in search.
+    pub external: bool,
+}
+
+impl EngineSettings {
+    fn from_args() -> Self {
+        let color = match arg_value("--engine").as_deref() {
+            Some("white") => Some(Color::White),
+            Some("black") => Some(Color::Black),
+            _ => None,
+        };
+        let mut limits = SearchLimits::default();
+        if let Some(depth) = arg_value("--engine-depth").and_then(|depth| depth.parse().ok()) {
+            limits.max_depth = depth;
+        }
+        if let Some(millis) = arg_value("--engine-time").and_then(|millis| millis.parse().ok()) {
+            limits.time = Duration::from_millis(millis);
+        }
+        Self {
+            color,
+            limits,
+            external: false,
+        }
+    }
+
+    /// Whether the engine is the one to move on `board`.
+    pub fn plays(&self, board: &Board) -> bool {
+        self.color == Some(board.side_to_move())
+    }
+
+    /// The limits for the next move of `game`, leaving time on a running clock for the rest
+    /// of the game.
+    pub fn limits_for(&self, game: &Game) -> SearchLimits {
+        let mut limits = self.limits;
+        if game.is_running() {
+            let side = game.board().side_to_move();
+            limits.time = limits.time.min(game.remaining(side) / 20);
+        }
+        limits
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub max_depth: u8,
+    pub time: Duration,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            time: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: ChessMove,
+    /// Centipawns for the side to move.
+    pub score: i32,
+    /// The deepest search that completed.
+    pub depth: u8,
+}
+
+/// Searches `board` with iterative deepening until `limits` are reached, returning the best
+/// move of the deepest completed search, or `None` without legal moves.
+pub fn search(board: &Board, limits: &SearchLimits) -> Option<SearchResult> {
+    let mut searcher = Searcher {
+        tt: HashMap::new(),
+        deadline: Instant::now() + limits.time,
+        nodes: 0,
+        stopped: false,
+    };
+    // Any legal move beats none if even the first iteration runs out of time
+    let mut best = MoveGen::new_legal(board)
+        .next()
+        .map(|best_move| SearchResult {
+            best_move,
+            score: 0,
+            depth: 0,
+        });
+
+    for depth in 1..=limits.max_depth {
+        let score = searcher.negamax(board, depth, -INFINITY, INFINITY, 0);
+        if searcher.stopped {
+            break;
+        }
+        let best_move = searcher
+            .tt
+            .get(&board.get_hash())
+            .and_then(|entry| entry.best_move);
+        if let Some(best_move) = best_move {
+            best = Some(SearchResult {
+                best_move,
+                score,
+                depth,
+            });
+        }
+        if score.abs() > MATE_THRESHOLD {
+            break;
+        }
+    }
+    best
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    /// The score is at least this, the search failed high.
+    Lower,
+    /// The score is at most this, the search failed low.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TtEntry {
+    depth: u8,
+    score: i32,
+    bound: Bound,
+    best_move: Option<ChessMove>,
+}
+
+struct Searcher {
+    /// Transposition table keyed on `Board::get_hash`.
+    tt: HashMap<u64, TtEntry>,
+    deadline: Instant,
+    nodes: u64,
+    stopped: bool,
+}
+
+impl Searcher {
+    fn out_of_time(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes % NODES_PER_CLOCK_CHECK == 0 && Instant::now() >= self.deadline {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    fn negamax(&mut self, board: &Board, depth: u8, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+        if self.out_of_time() {
+            return 0;
+        }
+        // Generated once, for mate and stalemate as well as for the search
+        let mut moves: Vec<ChessMove> = MoveGen::new_legal(board).collect();
+        if moves.is_empty() {
+            return if board.checkers().popcnt() > 0 {
+                -(MATE - ply)
+            } else {
+                0
+            };
+        }
+        if depth == 0 {
+            return self.quiescence(board, alpha, beta);
+        }
+
+        let hash = board.get_hash();
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.get(&hash) {
+            tt_move = entry.best_move;
+            let score = score_from_tt(entry.score, ply);
+            // The root always searches to find its best move
+            if entry.depth >= depth && ply > 0 {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        let alpha_before = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        order_moves(board, &mut moves, tt_move);
+        for m in moves {
+            let score = -self.negamax(&board.make_move_new(m), depth - 1, -beta, -alpha, ply + 1);
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(m);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_score <= alpha_before {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.tt.insert(
+            hash,
+            TtEntry {
+                depth,
+                score: score_to_tt(best_score, ply),
+                bound,
+                best_move,
+            },
+        );
+        best_score
+    }
+
+    /// Searches captures only, so the evaluation is not taken in the middle of an exchange.
+    fn quiescence(&mut self, board: &Board, mut alpha: i32, beta: i32) -> i32 {
+        if self.out_of_time() {
+            return 0;
+        }
+        let stand_pat = evaluate(board);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut captures = MoveGen::new_legal(board);
+        captures.set_iterator_mask(*board.color_combined(!board.side_to_move()));
+        let mut captures: Vec<ChessMove> = captures.collect();
+        captures.sort_by_key(|&m| -order_score(board, m, None));
+        for m in captures {
+            let score = -self.quiescence(&board.make_move_new(m), -beta, -alpha);
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+/// Mate scores are stored relative to the node, so they stay right wherever it is reached.
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score > MATE_THRESHOLD {
+        score + ply
+    } else if score < -MATE_THRESHOLD {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score > MATE_THRESHOLD {
+        score - ply
+    } else if score < -MATE_THRESHOLD {
+        score + ply
+    } else {
+        score
+    }
+}
+
+/// Sorts the legal `moves` the most promising first: the transposition table move, captures
+/// of valuable pieces by cheap ones, then promotions.
+fn order_moves(board: &Board, moves: &mut [ChessMove], tt_move: Option<ChessMove>) {
+    moves.sort_by_key(|&m| -order_score(board, m, tt_move));
+}
+
+fn order_score(board: &Board, m: ChessMove, tt_move: Option<ChessMove>) -> i32 {
+    if Some(m) == tt_move {
+        return 1_000_000;
+    }
+    let mut score = 0;
+    if let Some(victim) = board.piece_on(m.get_dest()) {
+        let attacker = board.piece_on(m.get_source()).unwrap_or(Piece::Pawn);
+        score += 100_000 + 10 * piece_value(victim) - piece_value(attacker);
+    }
+    if let Some(promotion) = m.get_promotion() {
+        score += 50_000 + piece_value(promotion);
+    }
+    score
+}
+
+fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 320,
+        Piece::Bishop => 330,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 0,
+    }
+}
+
+// Piece-square tables from White's side, rank 8 first
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+fn piece_square_table(piece: Piece) -> &'static [i32; 64] {
+    match piece {
+        Piece::Pawn => &PAWN_TABLE,
+        Piece::Knight => &KNIGHT_TABLE,
+        Piece::Bishop => &BISHOP_TABLE,
+        Piece::Rook => &ROOK_TABLE,
+        Piece::Queen => &QUEEN_TABLE,
+        Piece::King => &KING_TABLE,
+    }
+}
+
+/// Material and piece-square evaluation in centipawns, for the side to move.
+pub fn evaluate(board: &Board) -> i32 {
+    let mut score = 0;
+    for piece in ALL_PIECES {
+        let table = piece_square_table(piece);
+        for color in [Color::White, Color::Black] {
+            for sq in *board.pieces(piece) & *board.color_combined(color) {
+                let (rank, file) = (sq.get_rank().to_index(), sq.get_file().to_index());
+                // The tables list rank 8 first, as White sees the board
+                let row = match color {
+                    Color::White => 7 - rank,
+                    Color::Black => rank,
+                };
+                let value = piece_value(piece) + table[row * 8 + file];
+                score += match color {
+                    Color::White => value,
+                    Color::Black => -value,
+                };
+            }
+        }
+    }
+    match board.side_to_move() {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+/// E switches the engine from nobody to Black, to White and back to nobody.
+fn cycle_engine_side(
+    keys: Res<Input<KeyCode>>,
+    mut settings: ResMut<EngineSettings>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    if !keys.just_pressed(KeyCode::E) {
+        return;
+    }
+    let (color, status) = match settings.color {
+        None => (Some(Color::Black), "Engine plays Black"),
+        Some(Color::Black) => (Some(Color::White), "Engine plays White"),
+        Some(Color::White) => (None, "Engine off"),
+    };
+    settings.color = color;
+    status_evw.send(StatusMessage(status.to_string()));
+}
+
+/// A search running on the async compute pool.
+struct Thinking {
+    /// The hash of the position searched.
+    hash: u64,
+    result: Mutex<Receiver<Option<SearchResult>>>,
+}
+
+/// Thinks on a background task when it is the engine's turn, then selects its move for
+/// `handle_chess_move` like a player would.
+fn engine_move(
+    mut thinking: Local<Option<Thinking>>,
+    settings: Res<EngineSettings>,
+    replay: Res<Replay>,
+    game: Res<Game>,
+    square_q: Query<&SquareComponent>,
+    mut selected_q: Query<&mut SelectingSquares>,
+) {
+    let board = *game.board();
+    let mut selected = selected_q.single_mut();
+
+    if let Some(search) = thinking.as_ref() {
+        let received = search.result.lock().unwrap().try_recv();
+        let searched = search.hash;
+        match received {
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) | Ok(None) => {}
+            // The position may have changed meanwhile, by a takeback for instance
+            Ok(Some(result)) => {
+                if searched == board.get_hash() && settings.plays(&board) {
+                    select_move(&mut selected, &game, &square_q, result.best_move);
+                }
+            }
+        }
+        *thinking = None;
+        return;
+    }
+
+    let waiting = selected.end.is_some() || selected.promotion.is_some();
+    if !settings.plays(&board) || settings.external {
+        return;
+    }
+    if game.result().is_some() || replay.is_active() || waiting {
+        return;
+    }
+
+    let limits = settings.limits_for(&game);
+    let (sender, receiver) = mpsc::channel();
+    AsyncComputeTaskPool::get()
+        .spawn(async move {
+            // The receiver is gone if the app exited meanwhile
+            let _ = sender.send(search(&board, &limits));
+        })
+        .detach();
+    *thinking = Some(Thinking {
+        hash: board.get_hash(),
+        result: Mutex::new(receiver),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, search, SearchLimits};
+    use chess::{Board, ChessMove};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    fn best_move(fen: &str, max_depth: u8) -> ChessMove {
+        let limits = SearchLimits {
+            max_depth,
+            time: Duration::from_secs(60),
+        };
+        search(&Board::from_str(fen).unwrap(), &limits)
+            .unwrap()
+            .best_move
+    }
+
+    #[test]
+    fn evaluates_symmetric_positions_as_equal() {
+        assert_eq!(evaluate(&Board::default()), 0);
+        let e4 = Board::from_str("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert!(evaluate(&e4.unwrap()) < 0);
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let back_rank = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(
+            best_move(back_rank, 4),
+            ChessMove::from_str("a1a8").unwrap()
+        );
+    }
+
+    #[test]
+    fn takes_a_hanging_queen() {
+        let hanging = "rnb1kbnr/pppp1ppp/8/4p3/4P2q/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3";
+        assert_eq!(best_move(hanging, 3), ChessMove::from_str("f3h4").unwrap());
+    }
+
+    #[test]
+    fn returns_nothing_without_legal_moves() {
+        let stalemate = Board::from_str("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(search(&stalemate, &SearchLimits::default()).is_none());
+    }
+}
@@ -0,0 +1,208 @@
+use std::{fmt, path::Path, str::FromStr};
+
+/// The halfmove clock and fullmove number, which FEN carries but `chess::Board` does not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveCounters {
+    /// Halfmoves since the last capture or pawn move, for the fifty-move rule.
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+}
+
+impl Default for MoveCounters {
+    fn default() -> Self {
+        Self {
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+}
+
+impl MoveCounters {
+    /// The counters once `m` has been played on `board`.
+    pub fn after_move(self, board: &chess::Board, m: chess::ChessMove) -> Self {
+        let capture = board.piece_on(m.get_dest()).is_some();
+        let pawn_move = board.piece_on(m.get_source()) == Some(chess::Piece::Pawn);
+        let black_moved = board.side_to_move() == chess::Color::Black;
+        Self {
+            halfmove_clock: if capture || pawn_move {
+                0
+            } else {
+                self.halfmove_clock + 1
+            },
+            fullmove_number: self.fullmove_number + black_moved as u32,
+        }
+    }
+}
+
+/// A board with its move counters, i.e. everything a FEN string describes. The default is
+/// the starting position, as `chess::Board::default()` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    pub board: chess::Board,
+    pub counters: MoveCounters,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // `chess::Board` writes placeholder counters after the first four fields
+        let board = self.board.to_string();
+        let fields: Vec<&str> = board.split_whitespace().take(4).collect();
+        write!(
+            f,
+            "{} {} {}",
+            fields.join(" "),
+            self.counters.halfmove_clock,
+            self.counters.fullmove_number
+        )
+    }
+}
+
+/// Why a FEN string could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenError {
+    pub fen: String,
+    pub reason: String,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid FEN \"{}\": {}", self.fen, self.reason)
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// Parses a FEN string, checking its layout first so a malformed string is reported by what
+/// is wrong with it.
+pub fn parse_fen(fen: &str) -> Result<Position, FenError> {
+    let error = |reason: String| FenError {
+        fen: fen.trim().to_string(),
+        reason,
+    };
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() != 4 && fields.len() != 6 {
+        return Err(error(format!(
+            "expected 6 fields separated by spaces, found {}",
+            fields.len()
+        )));
+    }
+
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+    if ranks.len() != 8 {
+        return Err(error(format!("expected 8 ranks, found {}", ranks.len())));
+    }
+    for (i, rank) in ranks.iter().enumerate() {
+        let mut files = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => files += c as usize - '0' as usize,
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => files += 1,
+                _ => return Err(error(format!("unknown piece '{}' on rank {}", c, 8 - i))),
+            }
+        }
+        if files != 8 {
+            return Err(error(format!("rank {} has {} squares", 8 - i, files)));
+        }
+    }
+
+    if fields[1] != "w" && fields[1] != "b" {
+        return Err(error(format!(
+            "side to move must be 'w' or 'b', found '{}'",
+            fields[1]
+        )));
+    }
+    if fields[2] != "-" && !fields[2].chars().all(|c| "KQkq".contains(c)) {
+        return Err(error(format!("invalid castling rights '{}'", fields[2])));
+    }
+    if fields[3] != "-" && chess::Square::from_str(fields[3]).is_err() {
+        return Err(error(format!("invalid en passant square '{}'", fields[3])));
+    }
+    let mut counters = MoveCounters::default();
+    if fields.len() == 6 {
+        let counter = |field: &str| {
+            field
+                .parse::<u32>()
+                .map_err(|_| error(format!("invalid move counter '{}'", field)))
+        };
+        counters.halfmove_clock = counter(fields[4])?;
+        counters.fullmove_number = counter(fields[5])?;
+    }
+
+    let board = chess::Board::from_str(&fields.join(" ")).map_err(|err| error(err.to_string()))?;
+    Ok(Position { board, counters })
+}
+
+/// Reads the first non-empty line of a file as a FEN string.
+pub fn read_fen_file(path: &Path) -> Result<Position, FenError> {
+    let contents = std::fs::read_to_string(path).map_err(|err| FenError {
+        fen: String::new(),
+        reason: format!("cannot read {}: {}", path.display(), err),
+    })?;
+    let fen = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    parse_fen(fen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_fen, MoveCounters, Position};
+    use std::str::FromStr;
+
+    #[test]
+    fn parses_start_position() {
+        let position = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(position.unwrap(), Position::default());
+    }
+
+    #[test]
+    fn keeps_side_to_move_and_en_passant() {
+        let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+        let position = parse_fen(fen).unwrap();
+        assert_eq!(position.to_string(), fen);
+        let board = position.board;
+        assert_eq!(board.side_to_move(), chess::Color::Black);
+        assert_eq!(board.en_passant(), Some(chess::Square::E4));
+    }
+
+    #[test]
+    fn reports_what_is_wrong() {
+        let reason = |fen: &str| parse_fen(fen).unwrap_err().reason;
+        assert_eq!(
+            reason("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            "unknown piece '9' on rank 6"
+        );
+        assert_eq!(
+            reason("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            "expected 8 ranks, found 7"
+        );
+        assert_eq!(
+            reason("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"),
+            "side to move must be 'w' or 'b', found 'x'"
+        );
+        assert!(parse_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn counts_moves() {
+        let mut position = Position::default();
+        for uci in ["g1f3", "g8f6", "f3g1", "e7e6"] {
+            let m = chess::ChessMove::from_str(uci).unwrap();
+            position.counters = position.counters.after_move(&position.board, m);
+            position.board = position.board.make_move_new(m);
+        }
+        assert_eq!(
+            position.counters,
+            MoveCounters {
+                halfmove_clock: 0,
+                fullmove_number: 3,
+            }
+        );
+        assert_eq!(
+            position.to_string(),
+            "rnbqkb1r/pppp1ppp/4pn2/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 3"
+        );
+    }
+}
@@ -0,0 +1,612 @@
+use bevy::{time::Stopwatch, utils::Duration};
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece, Square};
+use std::{fmt, str::FromStr};
+
+use crate::{
+    fen::{MoveCounters, Position},
+    history::{MoveHistory, MoveRecord},
+    san::to_san,
+    time_control::{Increment, TimeControl},
+};
+
+/// A game of chess: the board, the moves played, the clocks and the result. The rules live
+/// here, away from rendering, and the Bevy systems only turn input into calls on it.
+#[derive(Clone)]
+pub struct Game {
+    board: Board,
+    counters: MoveCounters,
+    history: MoveHistory,
+    clocks: Clocks,
+    result: Option<GameResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    GameOver,
+    Illegal(ChessMove),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::GameOver => write!(f, "the game is over"),
+            Self::Illegal(m) => write!(f, "illegal move {}", m),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+impl Game {
+    /// A game from `start` with the clocks not started yet.
+    pub fn new(start: Position, time_control: TimeControl) -> Self {
+        Self {
+            board: start.board,
+            counters: start.counters,
+            history: MoveHistory::new(start),
+            clocks: Clocks::new(time_control),
+            result: GameResult::from_board(&start.board),
+        }
+    }
+
+    /// The game after the `records` played from `start`, keeping their clock times, with the
+    /// clocks stopped.
+    pub fn replayed(start: Position, records: &[MoveRecord], time_control: TimeControl) -> Self {
+        let mut game = Self::new(start, time_control);
+        for record in records {
+            let played = ChessMove::from_str(&record.uci)
+                .ok()
+                .and_then(|m| game.try_move(m).ok());
+            match played {
+                Some(_) => *game.history.moves.last_mut().unwrap() = record.clone(),
+                None => break,
+            }
+        }
+        game
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn position(&self) -> Position {
+        Position {
+            board: self.board,
+            counters: self.counters,
+        }
+    }
+
+    pub fn history(&self) -> &MoveHistory {
+        &self.history
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    pub fn time_control(&self) -> &TimeControl {
+        &self.clocks.time_control
+    }
+
+    /// Resets the clocks to a new time control.
+    pub fn set_time_control(&mut self, time_control: TimeControl) {
+        self.clocks = Clocks::new(time_control);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.clocks.is_running
+    }
+
+    /// Starts the clocks, unless the game is already over.
+    pub fn start(&mut self) {
+        self.clocks.is_started = true;
+        self.clocks.is_running = self.result.is_none();
+    }
+
+    /// Takes over from `current` after a takeback, with the times this game had: the clocks
+    /// run if `current` was started.
+    pub fn resume_from(&mut self, current: &Game) {
+        if current.clocks.is_started {
+            self.start();
+        } else {
+            self.clocks.is_running = false;
+        }
+    }
+
+    pub fn remaining(&self, color: Color) -> Duration {
+        self.clocks.remaining(color)
+    }
+
+    pub fn legal_moves_from(&self, from: Square) -> Vec<ChessMove> {
+        MoveGen::new_legal(&self.board)
+            .filter(|m| m.get_source() == from)
+            .collect()
+    }
+
+    /// The squares the piece on `from` can legally move to.
+    pub fn legal_destinations(&self, from: Square) -> Vec<Square> {
+        let mut destinations: Vec<Square> = self
+            .legal_moves_from(from)
+            .iter()
+            .map(|m| m.get_dest())
+            .collect();
+        // Promotions give one move per piece to the same square
+        destinations.dedup();
+        destinations
+    }
+
+    /// Whether `from` -> `to` is a legal pawn move to the last rank, which needs a piece.
+    pub fn is_promotion(&self, from: Square, to: Square) -> bool {
+        self.legal_moves_from(from)
+            .iter()
+            .any(|m| m.get_dest() == to && m.get_promotion().is_some())
+    }
+
+    /// Plays `m`, recording it and running the clocks on to the other side.
+    pub fn try_move(&mut self, m: ChessMove) -> Result<(), MoveError> {
+        if self.result.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if !self.board.legal(m) {
+            return Err(MoveError::Illegal(m));
+        }
+
+        let color = self.board.side_to_move();
+        let fen_before = self.position().to_string();
+        let san = to_san(&self.board, m);
+        self.counters = self.counters.after_move(&self.board, m);
+        self.board = self.board.make_move_new(m);
+        if self.clocks.is_running {
+            self.clocks.complete_move(color);
+        }
+        self.history.moves.push(MoveRecord {
+            san,
+            uci: m.to_string(),
+            fen_before,
+            white_clock: self.remaining(Color::White),
+            black_clock: self.remaining(Color::Black),
+        });
+        if let Some(result) = GameResult::from_board(&self.board) {
+            self.finish(result);
+        }
+        Ok(())
+    }
+
+    /// Runs the clock of the side to move, which loses once it runs out.
+    pub fn tick(&mut self, delta: Duration) {
+        if !self.clocks.is_running {
+            return;
+        }
+        let side = self.board.side_to_move();
+        self.clocks.tick(side, delta);
+
+        if self.remaining(side) == Duration::ZERO {
+            let result = if cannot_checkmate(&self.board, !side) {
+                GameResult::Draw(GameEndReason::TimeoutVsInsufficientMaterial)
+            } else {
+                GameResult::win(!side, GameEndReason::Timeout)
+            };
+            self.finish(result);
+        }
+    }
+
+    /// Records the result and stops the clocks.
+    fn finish(&mut self, result: GameResult) {
+        self.result = Some(result);
+        self.clocks.is_running = false;
+    }
+}
+
+#[derive(Clone)]
+struct Clocks {
+    time_control: TimeControl,
+    /// Time charged to each side.
+    white_watch: Stopwatch,
+    black_watch: Stopwatch,
+    /// Time given to each side so far: stage base times plus increments.
+    white_allotted: Duration,
+    black_allotted: Duration,
+    white_moves: u32,
+    black_moves: u32,
+    /// Time spent on the move in progress, for delays.
+    move_watch: Stopwatch,
+    /// The clocks were started, even if the game is over since.
+    is_started: bool,
+    is_running: bool,
+}
+
+impl Clocks {
+    fn new(time_control: TimeControl) -> Self {
+        Self {
+            white_allotted: time_control.stage(Color::White, 0).base,
+            black_allotted: time_control.stage(Color::Black, 0).base,
+            time_control,
+            white_watch: Stopwatch::new(),
+            black_watch: Stopwatch::new(),
+            white_moves: 0,
+            black_moves: 0,
+            move_watch: Stopwatch::new(),
+            is_started: false,
+            is_running: false,
+        }
+    }
+
+    fn side_mut(&mut self, color: Color) -> (&mut Stopwatch, &mut Duration, &mut u32) {
+        match color {
+            Color::White => (
+                &mut self.white_watch,
+                &mut self.white_allotted,
+                &mut self.white_moves,
+            ),
+            Color::Black => (
+                &mut self.black_watch,
+                &mut self.black_allotted,
+                &mut self.black_moves,
+            ),
+        }
+    }
+
+    fn moves(&self, color: Color) -> u32 {
+        match color {
+            Color::White => self.white_moves,
+            Color::Black => self.black_moves,
+        }
+    }
+
+    fn remaining(&self, color: Color) -> Duration {
+        let (watch, allotted) = match color {
+            Color::White => (&self.white_watch, self.white_allotted),
+            Color::Black => (&self.black_watch, self.black_allotted),
+        };
+        allotted
+            .checked_sub(watch.elapsed())
+            .unwrap_or_else(|| Duration::from_secs(0))
+    }
+
+    /// Runs `color`'s clock, holding it back during a simple delay.
+    fn tick(&mut self, color: Color, delta: Duration) {
+        let delay = match self.time_control.stage(color, self.moves(color)).increment {
+            Increment::Simple(delay) => delay,
+            _ => Duration::from_secs(0),
+        };
+        let before = self.move_watch.elapsed().saturating_sub(delay);
+        self.move_watch.tick(delta);
+        let charged = self.move_watch.elapsed().saturating_sub(delay) - before;
+        self.side_mut(color).0.tick(charged);
+    }
+
+    /// Applies the increment of the move `color` just completed and enters the next stage.
+    fn complete_move(&mut self, color: Color) {
+        let moves = self.moves(color);
+        let bonus = match self.time_control.stage(color, moves).increment {
+            Increment::Fischer(increment) => increment,
+            Increment::Bronstein(delay) => self.move_watch.elapsed().min(delay),
+            Increment::None | Increment::Simple(_) => Duration::from_secs(0),
+        };
+        let next_stage = self
+            .time_control
+            .stage_starting_after(color, moves + 1)
+            .map_or_else(|| Duration::from_secs(0), |stage| stage.base);
+
+        let (_, allotted, moves) = self.side_mut(color);
+        *allotted += bonus + next_stage;
+        *moves += 1;
+        self.move_watch.reset();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEndReason {
+    Checkmate,
+    Stalemate,
+    /// A flag fell.
+    Timeout,
+    /// A flag fell but the opponent could never have checkmated.
+    TimeoutVsInsufficientMaterial,
+}
+
+impl GameEndReason {
+    pub fn is_timeout(self) -> bool {
+        matches!(self, Self::Timeout | Self::TimeoutVsInsufficientMaterial)
+    }
+}
+
+impl fmt::Display for GameEndReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            Self::Checkmate => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::Timeout => "timeout",
+            Self::TimeoutVsInsufficientMaterial => "timeout vs insufficient material",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins(GameEndReason),
+    BlackWins(GameEndReason),
+    Draw(GameEndReason),
+}
+
+impl GameResult {
+    /// The result of the game on `board`, or `None` while it is still going.
+    pub fn from_board(board: &Board) -> Option<Self> {
+        match board.status() {
+            BoardStatus::Ongoing => None,
+            BoardStatus::Stalemate => Some(Self::Draw(GameEndReason::Stalemate)),
+            BoardStatus::Checkmate => {
+                Some(Self::win(!board.side_to_move(), GameEndReason::Checkmate))
+            }
+        }
+    }
+
+    fn win(winner: Color, reason: GameEndReason) -> Self {
+        match winner {
+            Color::White => Self::WhiteWins(reason),
+            Color::Black => Self::BlackWins(reason),
+        }
+    }
+
+    /// The score as in a PGN result tag, e.g. `1-0`.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::WhiteWins(_) => "1-0",
+            Self::BlackWins(_) => "0-1",
+            Self::Draw(_) => "1/2-1/2",
+        }
+    }
+
+    pub fn reason(&self) -> GameEndReason {
+        match *self {
+            Self::WhiteWins(reason) | Self::BlackWins(reason) | Self::Draw(reason) => reason,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let headline = match self {
+            Self::WhiteWins(_) => "White wins",
+            Self::BlackWins(_) => "Black wins",
+            Self::Draw(_) => "Draw",
+        };
+        write!(f, "{}\nby {}", headline, self.reason())
+    }
+}
+
+/// Whether `color` can never checkmate: it has a bare king, a king and a single minor piece
+/// against a bare king, or the kings are left with bishops that all stand on one square
+/// colour.
+fn cannot_checkmate(board: &Board, color: Color) -> bool {
+    let pieces = |color: Color| board.color_combined(color).popcnt();
+    let minors = (*board.pieces(Piece::Knight) | *board.pieces(Piece::Bishop))
+        & *board.color_combined(color);
+    let bishops = *board.pieces(Piece::Bishop);
+    let only_bishops = *board.combined() == bishops | *board.pieces(Piece::King);
+    let light = |sq: &Square| (sq.get_rank().to_index() + sq.get_file().to_index()) % 2 == 1;
+    let light_bishops = bishops.filter(light).count() as u32;
+    let one_square_colour = light_bishops == 0 || light_bishops == bishops.popcnt();
+    match pieces(color) {
+        1 => true,
+        2 if minors.popcnt() == 1 && pieces(!color) == 1 => true,
+        _ => only_bishops && one_square_colour,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Game, GameEndReason, GameResult, MoveError};
+    use crate::fen::{parse_fen, Position};
+    use crate::time_control::{Increment, TimeControl};
+    use bevy::utils::Duration;
+    use chess::{ChessMove, Piece, Square};
+    use std::str::FromStr;
+
+    fn game(fen: &str) -> Game {
+        Game::new(parse_fen(fen).unwrap(), TimeControl::default())
+    }
+
+    fn play(game: &mut Game, uci: &str) -> Result<(), MoveError> {
+        game.try_move(ChessMove::from_str(uci).unwrap())
+    }
+
+    #[test]
+    fn records_moves() {
+        let mut game = Game::new(Position::default(), TimeControl::default());
+        assert_eq!(
+            game.legal_destinations(Square::G1),
+            [Square::F3, Square::H3]
+        );
+        assert!(game.legal_moves_from(Square::E1).is_empty());
+
+        play(&mut game, "e2e4").unwrap();
+        let illegal = ChessMove::from_str("e4e5").unwrap();
+        assert_eq!(game.try_move(illegal), Err(MoveError::Illegal(illegal)));
+        play(&mut game, "e7e5").unwrap();
+        play(&mut game, "g1f3").unwrap();
+
+        let sans: Vec<&str> = game
+            .history()
+            .moves
+            .iter()
+            .map(|r| r.san.as_str())
+            .collect();
+        assert_eq!(sans, ["e4", "e5", "Nf3"]);
+        assert_eq!(game.position().counters.halfmove_clock, 1);
+        assert_eq!(game.position().counters.fullmove_number, 2);
+    }
+
+    #[test]
+    fn asks_for_the_promotion_piece() {
+        let mut game = game("1r5k/P7/8/8/8/8/8/K7 w - - 0 1");
+        assert!(game.is_promotion(Square::A7, Square::B8));
+        assert!(game.is_promotion(Square::A7, Square::A8));
+        assert!(!game.is_promotion(Square::A1, Square::A2));
+        assert_eq!(play(&mut game, "a7b8q"), Ok(()));
+        assert_eq!(game.board().piece_on(Square::B8), Some(Piece::Queen));
+    }
+
+    #[test]
+    fn ends_on_checkmate() {
+        let mut game = game("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2");
+        game.start();
+        play(&mut game, "d8h4").unwrap();
+        assert_eq!(
+            game.result(),
+            Some(GameResult::BlackWins(GameEndReason::Checkmate))
+        );
+        assert!(!game.is_running());
+        assert_eq!(play(&mut game, "e2e3"), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn runs_the_clock_of_the_side_to_move() {
+        let blitz = TimeControl::sudden_death(
+            "3+2",
+            Duration::from_secs(180),
+            Increment::Fischer(Duration::from_secs(2)),
+        );
+        let mut game = Game::new(Position::default(), blitz);
+        game.tick(Duration::from_secs(5));
+        // Not started yet
+        assert_eq!(
+            game.remaining(chess::Color::White),
+            Duration::from_secs(180)
+        );
+
+        game.start();
+        game.tick(Duration::from_secs(5));
+        play(&mut game, "e2e4").unwrap();
+        game.tick(Duration::from_secs(7));
+        assert_eq!(
+            game.remaining(chess::Color::White),
+            Duration::from_secs(177)
+        );
+        assert_eq!(
+            game.remaining(chess::Color::Black),
+            Duration::from_secs(173)
+        );
+        assert_eq!(
+            game.history().moves[0].white_clock,
+            Duration::from_secs(177)
+        );
+
+        game.tick(Duration::from_secs(200));
+        assert_eq!(
+            game.result(),
+            Some(GameResult::WhiteWins(GameEndReason::Timeout))
+        );
+    }
+
+    #[test]
+    fn draws_on_time_against_a_bare_king() {
+        let flag_fall = |fen: &str| {
+            let mut game = game(fen);
+            game.start();
+            game.tick(Duration::from_secs(601));
+            game.result()
+        };
+        let draw = Some(GameResult::Draw(
+            GameEndReason::TimeoutVsInsufficientMaterial,
+        ));
+        let white_wins = Some(GameResult::WhiteWins(GameEndReason::Timeout));
+
+        assert_eq!(flag_fall("8/8/4k3/8/8/8/8/4K2N b - - 0 1"), draw);
+        // Bishops on dark squares only, whoever has them
+        assert_eq!(flag_fall("5b2/8/4k3/8/8/8/8/2B1K3 b - - 0 1"), draw);
+        assert_eq!(flag_fall("8/8/4k3/8/8/8/8/B1B1K3 b - - 0 1"), draw);
+        // Bishops on both colours, or a pawn to block its own king, can help a mate
+        assert_eq!(flag_fall("6b1/8/4k3/8/8/8/8/2B1K3 b - - 0 1"), white_wins);
+        assert_eq!(flag_fall("8/8/4k3/4p3/8/8/8/4K2N b - - 0 1"), white_wins);
+    }
+
+    fn preset(name: &str) -> TimeControl {
+        TimeControl::presets()
+            .into_iter()
+            .find(|tc| tc.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn gives_back_the_time_used_up_to_the_bronstein_delay() {
+        let mut game = Game::new(Position::default(), preset("5 br3"));
+        game.start();
+        game.tick(Duration::from_secs(2));
+        play(&mut game, "e2e4").unwrap();
+        game.tick(Duration::from_secs(5));
+        play(&mut game, "e7e5").unwrap();
+        assert_eq!(
+            game.remaining(chess::Color::White),
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            game.remaining(chess::Color::Black),
+            Duration::from_secs(298)
+        );
+    }
+
+    #[test]
+    fn waits_out_the_simple_delay_before_counting_down() {
+        let mut game = Game::new(Position::default(), preset("5 d5"));
+        game.start();
+        game.tick(Duration::from_secs(3));
+        assert_eq!(
+            game.remaining(chess::Color::White),
+            Duration::from_secs(300)
+        );
+        play(&mut game, "e2e4").unwrap();
+        game.tick(Duration::from_secs(4));
+        game.tick(Duration::from_secs(4));
+        play(&mut game, "e7e5").unwrap();
+        assert_eq!(
+            game.remaining(chess::Color::White),
+            Duration::from_secs(300)
+        );
+        assert_eq!(
+            game.remaining(chess::Color::Black),
+            Duration::from_secs(297)
+        );
+    }
+
+    #[test]
+    fn adds_the_next_stage_after_move_forty() {
+        let mut game = Game::new(Position::default(), preset("40/90+30"));
+        game.start();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for uci in shuffle.iter().cycle().take(78) {
+            play(&mut game, uci).unwrap();
+        }
+        // 39 moves each: 90 minutes and 39 increments
+        assert_eq!(
+            game.remaining(chess::Color::White),
+            Duration::from_secs(90 * 60 + 39 * 30)
+        );
+        play(&mut game, shuffle[78 % 4]).unwrap();
+        // The 40th move adds its increment and the 30 minutes of the second stage
+        assert_eq!(
+            game.remaining(chess::Color::White),
+            Duration::from_secs(120 * 60 + 40 * 30)
+        );
+        assert_eq!(
+            game.remaining(chess::Color::Black),
+            Duration::from_secs(90 * 60 + 39 * 30)
+        );
+    }
+
+    #[test]
+    fn resumes_the_clocks_after_a_takeback() {
+        let mut before = Game::new(Position::default(), TimeControl::default());
+        let mut current = before.clone();
+        play(&mut current, "e2e4").unwrap();
+        // Started after the move taken back
+        current.start();
+        before.resume_from(&current);
+        assert!(before.is_running());
+
+        let mut unstarted = Game::new(Position::default(), TimeControl::default());
+        unstarted.resume_from(&Game::new(Position::default(), TimeControl::default()));
+        assert!(!unstarted.is_running());
+    }
+}
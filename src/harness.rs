@@ -0,0 +1,598 @@
+//! A headless app running the game's systems, driven by mouse input as a player would.
+
+use bevy::{
+    input::{keyboard::KeyboardInput, mouse::MouseButtonInput, ButtonState, InputPlugin},
+    prelude::*,
+    utils::{Duration, Instant},
+    window::{WindowId, WindowResized},
+};
+use raw_window_handle::{RawWindowHandle, WebHandle};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    engine::{EngineSettings, SearchLimits},
+    fen::parse_fen,
+    game::Game,
+    handle_chess_move,
+    layout::{BoardLayout, LayoutPlugin},
+    load_position, mouse_select_system,
+    pieces::{AnimationSettings, FadeOut, PiecesPlugin, Slide},
+    promotion_choices, promotion_picker,
+    replay::{OpenPgn, ReplayPlugin},
+    spawn_board,
+    takeback::{TakebackPlugin, TakebackSettings},
+    theme::{ThemeFonts, Themes},
+    time_control::TimeControl,
+    timer_tick, BoardOrientation, ChessPieceSprites, LoadPosition, MoveAttempt, PieceComponent,
+    PieceSprite, SquareComponent, StatusMessage, HEIGHT, RESOLUTION, RIGHT_UI,
+};
+
+/// How long a frame lasts unless the test says otherwise.
+const FRAME_TIME: Duration = Duration::from_millis(10);
+
+/// `MinimalPlugins` and the board systems on a window that is never opened, so no GPU is
+/// needed. Every input is followed by a frame.
+pub struct TestApp {
+    app: App,
+}
+
+/// The time of the app, which only moves on with its frames, each `frame_time` long.
+struct TestClock {
+    time: Time,
+    now: Instant,
+    frame_time: Duration,
+}
+
+/// Replaces the wall clock time, updated at the start of the stage, with the test's own.
+fn step_time(mut clock: ResMut<TestClock>, mut time: ResMut<Time>) {
+    let now = clock.now + clock.frame_time;
+    clock.now = now;
+    clock.time.update_with_instant(now);
+    *time = clock.time.clone();
+}
+
+impl TestApp {
+    /// The position in `fen` with the clocks stopped and the startup window size.
+    pub fn new(fen: &str) -> Self {
+        let position = parse_fen(fen).unwrap();
+        let layout = BoardLayout::new(HEIGHT * RESOLUTION + RIGHT_UI, HEIGHT);
+        let descriptor = WindowDescriptor {
+            width: layout.window.x,
+            height: layout.window.y,
+            ..default()
+        };
+        let mut windows = Windows::default();
+        windows.add(Window::new(
+            WindowId::primary(),
+            &descriptor,
+            layout.window.x as u32,
+            layout.window.y as u32,
+            1.,
+            None,
+            RawWindowHandle::Web(WebHandle::empty()),
+        ));
+
+        let time = Time::default();
+        let clock = TestClock {
+            now: time.startup(),
+            time,
+            frame_time: FRAME_TIME,
+        };
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(InputPlugin)
+            .insert_resource(clock)
+            .add_system_to_stage(CoreStage::First, step_time)
+            .insert_resource(windows)
+            .insert_resource(layout)
+            .insert_resource(BoardOrientation::White)
+            .insert_resource(ChessPieceSprites(Handle::default()))
+            .init_resource::<Themes>()
+            .insert_resource(ThemeFonts {
+                font: Handle::default(),
+                coordinate_font: Handle::default(),
+            })
+            .insert_resource(Game::new(position, TimeControl::default()))
+            .insert_resource(TakebackSettings { confirm: false })
+            .insert_resource(EngineSettings {
+                color: None,
+                limits: SearchLimits::default(),
+                external: false,
+            })
+            .add_event::<StatusMessage>()
+            .add_event::<LoadPosition>()
+            .add_event::<MoveAttempt>()
+            .add_event::<WindowResized>()
+            .add_plugin(LayoutPlugin)
+            .add_plugin(PiecesPlugin)
+            .add_plugin(TakebackPlugin)
+            .add_plugin(ReplayPlugin)
+            .add_startup_system(spawn_board)
+            .add_system(timer_tick)
+            .add_system(mouse_select_system)
+            .add_system(handle_chess_move.after(mouse_select_system))
+            .add_system(promotion_picker.after(handle_chess_move))
+            .add_system(load_position);
+        app.update();
+        Self { app }
+    }
+
+    pub fn update(&mut self) {
+        self.app.update();
+    }
+
+    /// How long the frames of every following update last.
+    pub fn set_frame_time(&mut self, frame_time: Duration) {
+        self.app.world.resource_mut::<TestClock>().frame_time = frame_time;
+    }
+
+    /// Runs a single frame lasting `delta`.
+    pub fn advance(&mut self, delta: Duration) {
+        let frame_time = std::mem::replace(
+            &mut self.app.world.resource_mut::<TestClock>().frame_time,
+            delta,
+        );
+        self.app.update();
+        self.set_frame_time(frame_time);
+    }
+
+    pub fn game(&self) -> &Game {
+        self.app.world.resource::<Game>()
+    }
+
+    pub fn game_mut(&mut self) -> Mut<Game> {
+        self.app.world.resource_mut::<Game>()
+    }
+
+    fn square_position(&mut self, sq: chess::Square) -> Vec2 {
+        let mut square_q = self.app.world.query::<&SquareComponent>();
+        square_q
+            .iter(&self.app.world)
+            .find(|square| square.chess_sq == sq)
+            .map(|square| square.position)
+            .unwrap()
+    }
+
+    /// Moves the cursor to the centre of `sq`.
+    pub fn hover(&mut self, sq: chess::Square) {
+        let world = self.square_position(sq);
+        let layout = *self.app.world.resource::<BoardLayout>();
+        // The inverse of `BoardLayout::cursor_to_world`
+        let cursor = Vec2::new(
+            world.x + layout.window.x / 2. - layout.camera_offset(),
+            world.y + layout.window.y / 2.,
+        );
+        self.app
+            .world
+            .resource_mut::<Windows>()
+            .get_primary_mut()
+            .unwrap()
+            .update_cursor_physical_position_from_backend(Some(cursor.as_dvec2()));
+    }
+
+    fn mouse(&mut self, state: ButtonState) {
+        self.app
+            .world
+            .resource_mut::<Events<MouseButtonInput>>()
+            .send(MouseButtonInput {
+                button: MouseButton::Left,
+                state,
+            });
+        self.app.update();
+    }
+
+    /// Presses `keys` together and releases them.
+    pub fn press(&mut self, keys: &[KeyCode]) {
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            let mut keyboard = self.app.world.resource_mut::<Events<KeyboardInput>>();
+            for &key in keys {
+                keyboard.send(KeyboardInput {
+                    scan_code: 0,
+                    key_code: Some(key),
+                    state,
+                });
+            }
+            self.app.update();
+        }
+    }
+
+    /// Loads the position in `fen` as if it had been pasted.
+    pub fn load(&mut self, fen: &str) {
+        self.app
+            .world
+            .resource_mut::<Events<LoadPosition>>()
+            .send(LoadPosition(parse_fen(fen).unwrap()));
+        self.app.update();
+    }
+
+    /// Opens `pgn` for replay as if its file had been dropped on the window.
+    pub fn open_pgn(&mut self, pgn: &str) {
+        static OPENED: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "test-app-{}-{}.pgn",
+            std::process::id(),
+            OPENED.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, pgn).unwrap();
+        self.app
+            .world
+            .resource_mut::<Events<OpenPgn>>()
+            .send(OpenPgn(path.clone()));
+        self.app.update();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Resizes the window to `width` by `height`.
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.app
+            .world
+            .resource_mut::<Events<WindowResized>>()
+            .send(WindowResized {
+                id: WindowId::primary(),
+                width,
+                height,
+            });
+        self.app.update();
+    }
+
+    /// Whether a captured piece is still fading out.
+    pub fn fading(&mut self) -> bool {
+        let mut fade_q = self.app.world.query::<&FadeOut>();
+        fade_q.iter(&self.app.world).next().is_some()
+    }
+
+    /// Presses and releases the left button over `sq`.
+    pub fn click(&mut self, sq: chess::Square) {
+        self.hover(sq);
+        self.mouse(ButtonState::Pressed);
+        self.mouse(ButtonState::Released);
+    }
+
+    /// Drags the piece on `from` and drops it on `to`.
+    pub fn drag(&mut self, from: chess::Square, to: chess::Square) {
+        self.hover(from);
+        self.mouse(ButtonState::Pressed);
+        self.hover(to);
+        self.app.update();
+        self.mouse(ButtonState::Released);
+    }
+
+    /// Clicks the square of `piece` among the promotion choices shown on `end`.
+    pub fn pick_promotion(&mut self, end: chess::Square, piece: chess::Piece) {
+        let color = self.game().board().side_to_move();
+        let (sq, _) = promotion_choices(end, color)
+            .into_iter()
+            .find(|&(_, choice)| choice == piece)
+            .unwrap();
+        self.click(sq);
+    }
+
+    /// How long pieces take to move; zero moves them instantly.
+    pub fn set_animation(&mut self, duration: Duration) {
+        self.app.world.resource_mut::<AnimationSettings>().duration = duration;
+    }
+
+    /// Runs frames of `FRAME_TIME` until no piece sprite is sliding any more.
+    pub fn settle(&mut self) {
+        for _ in 0..100 {
+            let mut slide_q = self.app.world.query::<&Slide>();
+            if slide_q.iter(&self.app.world).next().is_none() {
+                return;
+            }
+            self.advance(FRAME_TIME);
+        }
+        panic!("the pieces are still sliding");
+    }
+
+    /// The square and sprite index of every piece sprite once they settled, in square order.
+    /// Panics if a sprite is not drawn on the square of its `PieceComponent`.
+    pub fn pieces(&mut self) -> Vec<(chess::Square, usize)> {
+        self.settle();
+        let squares: Vec<SquareComponent> = self
+            .app
+            .world
+            .query::<&SquareComponent>()
+            .iter(&self.app.world)
+            .cloned()
+            .collect();
+        let mut piece_q = self
+            .app
+            .world
+            .query::<(&PieceComponent, &Transform, &TextureAtlasSprite)>();
+        let mut pieces: Vec<_> = piece_q
+            .iter(&self.app.world)
+            .map(|(piece, transform, sprite)| {
+                let square = squares
+                    .iter()
+                    .find(|square| square.chess_sq == piece.square)
+                    .unwrap();
+                assert_eq!(transform.translation.truncate(), square.position);
+                (piece.square, sprite.index)
+            })
+            .collect();
+        pieces.sort_by_key(|&(sq, _)| sq.to_index());
+        pieces
+    }
+
+    /// Asserts that the sprites show the pieces of the game's board.
+    pub fn assert_pieces_match_board(&mut self) {
+        let board = *self.game().board();
+        let expected: Vec<_> = chess::ALL_SQUARES
+            .iter()
+            .filter_map(|&sq| {
+                let piece = board.piece_on(sq)?;
+                let color = board.color_on(sq)?;
+                Some((sq, PieceSprite::from_chess(piece, color) as usize))
+            })
+            .collect();
+        assert_eq!(self.pieces(), expected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestApp;
+    use crate::game::{GameEndReason, GameResult};
+    use crate::time_control::{Increment, TimeControl};
+    use bevy::{input::keyboard::KeyCode, utils::Duration};
+    use chess::{Piece, Square};
+
+    const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn plays_moves_by_clicking_and_dragging() {
+        let mut app = TestApp::new(START_FEN);
+        app.assert_pieces_match_board();
+
+        app.click(Square::E2);
+        app.click(Square::E4);
+        app.drag(Square::E7, Square::E5);
+
+        let moves: Vec<_> = app
+            .game()
+            .history()
+            .moves
+            .iter()
+            .map(|m| m.uci.as_str())
+            .collect();
+        assert_eq!(moves, ["e2e4", "e7e5"]);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn ignores_illegal_moves() {
+        let mut app = TestApp::new(START_FEN);
+        app.drag(Square::E2, Square::E5);
+        app.click(Square::E7);
+        app.click(Square::E5);
+
+        assert!(app.game().history().moves.is_empty());
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn removes_captured_pieces() {
+        let mut app = TestApp::new("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1");
+        app.click(Square::E4);
+        app.click(Square::D5);
+
+        assert_eq!(app.pieces().len(), 3);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn captures_en_passant() {
+        let mut app = TestApp::new("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+        app.drag(Square::E5, Square::D6);
+
+        assert_eq!(app.game().board().piece_on(Square::D5), None);
+        assert_eq!(app.pieces().len(), 3);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn moves_the_rook_when_castling() {
+        let mut app = TestApp::new("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1");
+        app.click(Square::E1);
+        app.click(Square::G1);
+        app.drag(Square::E8, Square::C8);
+
+        assert_eq!(app.game().board().piece_on(Square::F1), Some(Piece::Rook));
+        assert_eq!(app.game().board().piece_on(Square::D8), Some(Piece::Rook));
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn promotes_to_the_picked_piece() {
+        let mut app = TestApp::new("8/4P3/8/8/k7/8/8/4K3 w - - 0 1");
+        app.click(Square::E7);
+        app.click(Square::E8);
+        // Nothing is played until a piece is picked
+        assert!(app.game().history().moves.is_empty());
+
+        app.pick_promotion(Square::E8, Piece::Knight);
+        assert_eq!(app.game().board().piece_on(Square::E8), Some(Piece::Knight));
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn keeps_picking_the_promotion_when_the_window_is_resized() {
+        let mut app = TestApp::new("8/4P3/8/8/k7/8/8/4K3 w - - 0 1");
+        app.click(Square::E7);
+        app.click(Square::E8);
+        app.resize(600., 400.);
+        assert!(app.game().history().moves.is_empty());
+
+        app.pick_promotion(Square::E8, Piece::Knight);
+        assert_eq!(app.game().board().piece_on(Square::E8), Some(Piece::Knight));
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn ends_animations_when_the_window_is_resized() {
+        let mut app = TestApp::new("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1");
+        app.set_animation(Duration::from_secs(60));
+        app.click(Square::E4);
+        app.click(Square::D5);
+        assert!(app.fading());
+
+        app.resize(600., 400.);
+        assert!(!app.fading());
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn plays_moves_chosen_during_an_animation_once_it_ends() {
+        let mut app = TestApp::new(START_FEN);
+        app.set_animation(Duration::from_secs(60));
+        app.click(Square::E2);
+        app.click(Square::E4);
+        app.click(Square::E7);
+        app.click(Square::E5);
+        assert_eq!(app.game().history().moves.len(), 1);
+
+        app.set_animation(Duration::ZERO);
+        app.update();
+        app.update();
+        assert_eq!(app.game().history().moves.len(), 2);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn moves_pieces_at_once_when_instant() {
+        let mut app = TestApp::new("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1");
+        app.set_animation(Duration::ZERO);
+        app.click(Square::E1);
+        app.click(Square::C1);
+        app.drag(Square::E8, Square::G8);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn brings_captured_pieces_back_on_takebacks() {
+        let mut app = TestApp::new("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1");
+        app.click(Square::E4);
+        app.click(Square::D5);
+        app.press(&[KeyCode::LControl, KeyCode::Z]);
+
+        assert_eq!(app.game().board().piece_on(Square::D5), Some(Piece::Pawn));
+        assert_eq!(app.pieces().len(), 4);
+        app.assert_pieces_match_board();
+
+        app.press(&[KeyCode::LControl, KeyCode::Y]);
+        assert_eq!(app.pieces().len(), 3);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn shows_loaded_positions() {
+        let mut app = TestApp::new(START_FEN);
+        app.load("8/4P3/8/8/k7/8/8/4K3 w - - 0 1");
+        app.assert_pieces_match_board();
+
+        app.load(START_FEN);
+        assert_eq!(app.pieces().len(), 32);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn runs_the_clock_of_the_side_to_move() {
+        let mut app = TestApp::new(START_FEN);
+        let three_minutes = Duration::from_secs(180);
+        let increment = Increment::Fischer(Duration::from_secs(2));
+        app.game_mut()
+            .set_time_control(TimeControl::sudden_death("3+2", three_minutes, increment));
+        app.game_mut().start();
+        // Only the frames the test advances take time
+        app.set_frame_time(Duration::ZERO);
+
+        app.advance(Duration::from_secs(5));
+        app.click(Square::E2);
+        app.click(Square::E4);
+        let white = Duration::from_secs(177);
+        assert_eq!(app.game().remaining(chess::Color::White), white);
+
+        app.advance(Duration::from_secs(7));
+        assert_eq!(app.game().remaining(chess::Color::White), white);
+        assert_eq!(
+            app.game().remaining(chess::Color::Black),
+            Duration::from_secs(173)
+        );
+    }
+
+    #[test]
+    fn stops_input_when_a_flag_falls() {
+        let mut app = TestApp::new(START_FEN);
+        let tc = TimeControl::sudden_death("bullet", Duration::from_millis(20), Increment::None);
+        app.game_mut().set_time_control(tc);
+        app.game_mut().start();
+
+        app.advance(Duration::from_millis(30));
+        assert_eq!(
+            app.game().result(),
+            Some(GameResult::BlackWins(GameEndReason::Timeout))
+        );
+
+        app.click(Square::E2);
+        app.click(Square::E4);
+        assert!(app.game().history().moves.is_empty());
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn refuses_takebacks_once_a_flag_fell() {
+        let mut app = TestApp::new(START_FEN);
+        let tc = TimeControl::sudden_death("bullet", Duration::from_millis(20), Increment::None);
+        app.game_mut().set_time_control(tc);
+        app.click(Square::E2);
+        app.click(Square::E4);
+        app.game_mut().start();
+        app.advance(Duration::from_millis(30));
+        let flag_fall = Some(GameResult::WhiteWins(GameEndReason::Timeout));
+        assert_eq!(app.game().result(), flag_fall);
+
+        app.press(&[KeyCode::LControl, KeyCode::Z]);
+        assert_eq!(app.game().history().moves.len(), 1);
+        assert_eq!(app.game().result(), flag_fall);
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn returns_to_the_game_played_after_a_replay() {
+        let mut app = TestApp::new(START_FEN);
+        app.game_mut().start();
+        app.click(Square::E2);
+        app.click(Square::E4);
+
+        app.open_pgn("1. d4 d5 2. c4 *\n");
+        app.press(&[KeyCode::End]);
+        assert_eq!(app.game().history().moves.len(), 3);
+        assert!(!app.game().is_running());
+        app.assert_pieces_match_board();
+
+        app.press(&[KeyCode::Escape]);
+        assert_eq!(app.game().history().moves[0].uci, "e2e4");
+        assert_eq!(app.game().history().moves.len(), 1);
+        assert!(app.game().is_running());
+        app.assert_pieces_match_board();
+
+        // Along with its takebacks
+        app.press(&[KeyCode::LControl, KeyCode::Z]);
+        assert!(app.game().history().moves.is_empty());
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn keeps_the_clocks_running_after_taking_back_a_move_played_before_the_start() {
+        let mut app = TestApp::new(START_FEN);
+        app.click(Square::E2);
+        app.click(Square::E4);
+        app.game_mut().start();
+
+        app.press(&[KeyCode::LControl, KeyCode::Z]);
+        assert!(app.game().history().moves.is_empty());
+        assert!(app.game().is_running());
+    }
+}
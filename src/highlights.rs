@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use chess::ChessMove;
+use std::str::FromStr;
+
+use crate::{
+    game::Game,
+    layout::BoardLayout,
+    square_position,
+    theme::{Theme, Themes},
+    BoardOrientation,
+};
+
+/// Keeps the squares of the last move highlighted and marks the king in check in red, however
+/// the position came about: a move, a takeback, a replay or a load.
+pub struct HighlightsPlugin;
+
+impl Plugin for HighlightsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_highlights)
+            .add_system(update_highlights);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+enum Highlight {
+    LastMoveFrom,
+    LastMoveTo,
+    Check,
+}
+
+impl Highlight {
+    fn color(self, theme: &Theme) -> Color {
+        match self {
+            Self::LastMoveFrom | Self::LastMoveTo => theme.last_move,
+            Self::Check => theme.check,
+        }
+    }
+
+    /// The square highlighted in `game`, if any.
+    fn square(self, game: &Game) -> Option<chess::Square> {
+        let board = game.board();
+        let last_move = || {
+            let record = game.history().moves.last()?;
+            ChessMove::from_str(&record.uci).ok()
+        };
+        match self {
+            Self::LastMoveFrom => last_move().map(|m| m.get_source()),
+            Self::LastMoveTo => last_move().map(|m| m.get_dest()),
+            Self::Check if board.checkers().popcnt() > 0 => {
+                Some(board.king_square(board.side_to_move()))
+            }
+            Self::Check => None,
+        }
+    }
+}
+
+fn spawn_highlights(mut commands: Commands, themes: Res<Themes>) {
+    let highlights = [
+        (Highlight::LastMoveFrom, 1.5),
+        (Highlight::LastMoveTo, 1.5),
+        (Highlight::Check, 1.6),
+    ];
+    for (highlight, z) in highlights {
+        let color = highlight.color(themes.current());
+        // Between the squares and the selection
+        commands
+            .spawn()
+            .insert(Name::new(format!("{:?}", highlight)))
+            .insert(highlight)
+            .insert_bundle(SpriteBundle {
+                visibility: Visibility { is_visible: false },
+                sprite: Sprite { color, ..default() },
+                transform: Transform::from_xyz(0., 0., z),
+                ..default()
+            });
+    }
+}
+
+fn update_highlights(
+    game: Res<Game>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    themes: Res<Themes>,
+    mut highlight_q: Query<(&Highlight, &mut Visibility, &mut Transform, &mut Sprite)>,
+) {
+    if themes.is_changed() {
+        for (highlight, _, _, mut sprite) in highlight_q.iter_mut() {
+            sprite.color = highlight.color(themes.current());
+        }
+    }
+    if !game.is_changed() && !layout.is_changed() && !orientation.is_changed() {
+        return;
+    }
+    let piece_size = layout.piece_size();
+    for (highlight, mut visibility, mut transform, _) in highlight_q.iter_mut() {
+        let square = highlight.square(&game);
+        if visibility.is_visible != square.is_some() {
+            visibility.is_visible = square.is_some();
+        }
+        if let Some(square) = square {
+            let position = square_position(square, *orientation, piece_size);
+            transform.translation = position.extend(transform.translation.z);
+            transform.scale = Vec3::new(piece_size, piece_size, 1.);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Highlight;
+    use crate::fen::parse_fen;
+    use crate::game::Game;
+    use crate::time_control::TimeControl;
+    use chess::{ChessMove, Square};
+    use std::str::FromStr;
+
+    #[test]
+    fn highlights_the_last_move_and_the_king_in_check() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2";
+        let mut game = Game::new(parse_fen(fen).unwrap(), TimeControl::default());
+        assert_eq!(Highlight::LastMoveFrom.square(&game), None);
+        assert_eq!(Highlight::Check.square(&game), None);
+
+        game.try_move(ChessMove::from_str("d8h4").unwrap()).unwrap();
+        assert_eq!(Highlight::LastMoveFrom.square(&game), Some(Square::D8));
+        assert_eq!(Highlight::LastMoveTo.square(&game), Some(Square::H4));
+        assert_eq!(Highlight::Check.square(&game), Some(Square::E1));
+    }
+}
@@ -0,0 +1,190 @@
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    utils::Duration,
+};
+
+use crate::{
+    fen::Position,
+    game::Game,
+    layout::{BoardLayout, PanelItem, PanelSlot, ScaledText},
+    theme::ThemeFonts,
+    BoardOrientation, FONT_COLOR,
+};
+
+const MOVE_LIST_FONT_SIZE: f32 = 11.0;
+/// Rows of the move list shown at once.
+const MOVE_LIST_ROWS: usize = 8;
+
+/// Shows the moves played so far in a scrollable list in the side panel.
+pub struct MoveHistoryPlugin;
+
+impl Plugin for MoveHistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_move_list)
+            .add_system(update_move_list);
+    }
+}
+
+/// A move as it was played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRecord {
+    pub san: String,
+    pub uci: String,
+    pub fen_before: String,
+    /// Time left on each clock once the move was made.
+    pub white_clock: Duration,
+    pub black_clock: Duration,
+}
+
+/// Every move played from `start`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveHistory {
+    pub start: Position,
+    pub moves: Vec<MoveRecord>,
+}
+
+impl MoveHistory {
+    pub fn new(start: Position) -> Self {
+        Self {
+            start,
+            moves: Vec::new(),
+        }
+    }
+
+    /// The moves in numbered pairs, e.g. `1. e4 e5`, starting with `1... e5` when Black moved
+    /// first.
+    pub fn numbered_pairs(&self) -> Vec<String> {
+        let mut rows = Vec::new();
+        let mut number = self.start.counters.fullmove_number;
+        let mut moves = &self.moves[..];
+        if self.start.board.side_to_move() == chess::Color::Black && !moves.is_empty() {
+            rows.push(format!("{}... {}", number, moves[0].san));
+            number += 1;
+            moves = &moves[1..];
+        }
+        for pair in moves.chunks(2) {
+            let sans: Vec<&str> = pair.iter().map(|record| record.san.as_str()).collect();
+            rows.push(format!("{}. {}", number, sans.join(" ")));
+            number += 1;
+        }
+        rows
+    }
+}
+
+#[derive(Default, Component)]
+struct MoveList {
+    /// The first row shown, `None` to follow the latest move.
+    first_row: Option<usize>,
+}
+
+fn spawn_move_list(
+    mut commands: Commands,
+    fonts: Res<ThemeFonts>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+) {
+    let item = PanelItem::stacked(PanelSlot::MoveList);
+    let (position, size) = layout.place(&item, *orientation);
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: fonts.font.clone(),
+                    font_size: MOVE_LIST_FONT_SIZE * layout.scale,
+                    color: FONT_COLOR,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position,
+                size,
+                ..default()
+            }),
+        )
+        .insert(Name::new("MoveList"))
+        .insert(item)
+        .insert(ScaledText(MOVE_LIST_FONT_SIZE))
+        // Tracks the cursor so the wheel only scrolls the list when hovering it
+        .insert(Interaction::default())
+        .insert(MoveList::default());
+}
+
+fn update_move_list(
+    mut wheel_evr: EventReader<MouseWheel>,
+    game: Res<Game>,
+    mut shown_history: Local<Option<MoveHistory>>,
+    mut list_q: Query<(&Interaction, &mut MoveList, &mut Text)>,
+) {
+    let wheel: Vec<MouseWheel> = wheel_evr.iter().cloned().collect();
+    // The game changes with every clock tick, the moves only now and then
+    let moved = game.is_changed() && shown_history.as_ref() != Some(game.history());
+    if wheel.is_empty() && !moved {
+        return;
+    }
+    if moved {
+        *shown_history = Some(game.history().clone());
+    }
+    let rows = game.history().numbered_pairs();
+    let last_first_row = rows.len().saturating_sub(MOVE_LIST_ROWS);
+
+    for (interaction, mut list, mut text) in list_q.iter_mut() {
+        let mut scrolled = false;
+        for ev in &wheel {
+            if *interaction == Interaction::None {
+                continue;
+            }
+            let lines = match ev.unit {
+                MouseScrollUnit::Line => ev.y,
+                MouseScrollUnit::Pixel => ev.y / 20.,
+            };
+            let first_row = list.first_row.unwrap_or(last_first_row) as f32 - lines;
+            let first_row = first_row.round().clamp(0., last_first_row as f32) as usize;
+            list.first_row = if first_row == last_first_row {
+                None
+            } else {
+                Some(first_row)
+            };
+            scrolled = true;
+        }
+        if !scrolled && !moved {
+            continue;
+        }
+
+        let first_row = list.first_row.unwrap_or(last_first_row).min(last_first_row);
+        let shown = rows.iter().skip(first_row).take(MOVE_LIST_ROWS);
+        text.sections[0].value = shown.cloned().collect::<Vec<_>>().join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MoveHistory, MoveRecord};
+    use crate::fen::parse_fen;
+    use bevy::utils::Duration;
+
+    fn record(san: &str) -> MoveRecord {
+        MoveRecord {
+            san: san.to_string(),
+            uci: String::new(),
+            fen_before: String::new(),
+            white_clock: Duration::ZERO,
+            black_clock: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn numbers_move_pairs() {
+        let history = MoveHistory {
+            moves: ["e4", "e5", "Nf3"].into_iter().map(record).collect(),
+            ..MoveHistory::default()
+        };
+        assert_eq!(history.numbered_pairs(), ["1. e4 e5", "2. Nf3"]);
+
+        let start = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 7";
+        let mut history = MoveHistory::new(parse_fen(start).unwrap());
+        history.moves = ["e5", "Nf3", "Nc6"].into_iter().map(record).collect();
+        assert_eq!(history.numbered_pairs(), ["7... e5", "8. Nf3 Nc6"]);
+    }
+}
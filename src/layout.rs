@@ -0,0 +1,297 @@
+use bevy::{
+    prelude::*,
+    window::{WindowId, WindowResized},
+};
+
+use crate::{
+    square_position, BoardOrientation, PieceComponent, Promotion, SelectingEndSquare,
+    SelectingSquares, SelectingStartSquare, SquareComponent, HEIGHT, RESOLUTION, RIGHT_UI,
+};
+
+/// Keeps the board, pieces and side panel fitted to the window.
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(resize_layout)
+            .add_system(layout_board.after(resize_layout));
+    }
+}
+
+/// The top of the stacked widgets, relative to the middle of the window at the startup size.
+const STACK_TOP: f32 = -250.;
+/// The space between stacked widgets at the startup size.
+const PANEL_GAP: f32 = 5.;
+
+/// Sizes derived from the window: the board fills the height on the left and the side panel
+/// takes the remaining width.
+#[derive(Debug, Clone, Copy)]
+pub struct BoardLayout {
+    pub window: Vec2,
+    /// Relative to the startup window size, for fonts and panel widgets.
+    pub scale: f32,
+}
+
+impl BoardLayout {
+    pub fn new(width: f32, height: f32) -> Self {
+        let scale = (height / HEIGHT).min(width / (HEIGHT * RESOLUTION + RIGHT_UI));
+        Self {
+            window: Vec2::new(width, height),
+            scale,
+        }
+    }
+
+    pub fn board_size(&self) -> f32 {
+        HEIGHT * self.scale
+    }
+
+    pub fn piece_size(&self) -> f32 {
+        self.board_size() / 8.
+    }
+
+    pub fn panel_width(&self) -> f32 {
+        self.window.x - self.board_size()
+    }
+
+    /// The camera sits right of the board centre so the board is at the world origin.
+    pub fn camera_offset(&self) -> f32 {
+        self.panel_width() / 2.
+    }
+
+    /// Converts a window cursor position to world coordinates.
+    pub fn cursor_to_world(&self, cursor: Vec2) -> Vec2 {
+        Vec2::new(
+            cursor.x - self.window.x / 2. + self.camera_offset(),
+            cursor.y - self.window.y / 2.,
+        )
+    }
+
+    /// The position and size of a side panel widget.
+    pub fn place(
+        &self,
+        item: &PanelItem,
+        orientation: BoardOrientation,
+    ) -> (UiRect<Val>, Size<Val>) {
+        let width = RIGHT_UI * self.scale;
+        let height = item.height * self.scale;
+        let margin = 5. * self.scale;
+        let board_top = (self.window.y - self.board_size()) / 2.;
+        let top = match item.anchor {
+            PanelAnchor::Countdown(color) if orientation.bottom() == color => {
+                board_top + self.board_size() - height - margin
+            }
+            PanelAnchor::Countdown(_) => board_top + margin,
+            PanelAnchor::Stacked(slot) => self.window.y / 2. + slot.offset() * self.scale,
+        };
+        let position = UiRect {
+            top: Val::Px(top),
+            right: Val::Px((self.panel_width() - width) / 2.),
+            ..default()
+        };
+        (position, Size::new(Val::Px(width), Val::Px(height)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum PanelAnchor {
+    /// A side's clock, next to the edge of the board that side plays from.
+    Countdown(chess::Color),
+    /// A widget of the stack between the clocks.
+    Stacked(PanelSlot),
+}
+
+/// The widgets stacked between the clocks, from the top down. Each one's place follows from
+/// the heights of those above it, so a new widget only needs a slot here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelSlot {
+    MoveList,
+    CopyFen,
+    TimeControl,
+    Start,
+    Result,
+    Status,
+    Analysis,
+    Replay,
+}
+
+impl PanelSlot {
+    const STACK: [Self; 8] = [
+        Self::MoveList,
+        Self::CopyFen,
+        Self::TimeControl,
+        Self::Start,
+        Self::Result,
+        Self::Status,
+        Self::Analysis,
+        Self::Replay,
+    ];
+
+    /// Height at the startup size.
+    fn height(self) -> f32 {
+        match self {
+            Self::MoveList => 110.,
+            Self::CopyFen | Self::TimeControl => 40.,
+            Self::Start => 65.,
+            Self::Result => 50.,
+            Self::Status => 70.,
+            Self::Analysis => 30.,
+            Self::Replay => 55.,
+        }
+    }
+
+    /// The widget's top, relative to the middle of the window at the startup size.
+    fn offset(self) -> f32 {
+        let above: f32 = Self::STACK
+            .iter()
+            .take_while(|&&slot| slot != self)
+            .map(|slot| slot.height() + PANEL_GAP)
+            .sum();
+        STACK_TOP + above
+    }
+}
+
+/// A side panel widget, placed again whenever the layout changes.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct PanelItem {
+    pub anchor: PanelAnchor,
+    /// Height at the startup size.
+    pub height: f32,
+}
+
+impl PanelItem {
+    pub fn stacked(slot: PanelSlot) -> Self {
+        Self {
+            anchor: PanelAnchor::Stacked(slot),
+            height: slot.height(),
+        }
+    }
+}
+
+/// Text whose font size follows the layout scale.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct ScaledText(pub f32);
+
+fn resize_layout(mut resize_evr: EventReader<WindowResized>, mut layout: ResMut<BoardLayout>) {
+    for ev in resize_evr.iter() {
+        // Ignore minimising
+        if ev.id == WindowId::primary() && ev.width >= 1. && ev.height >= 1. {
+            *layout = BoardLayout::new(ev.width, ev.height);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn layout_board(
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    mut selected_q: Query<&mut SelectingSquares>,
+    mut set: ParamSet<(
+        Query<(&mut SquareComponent, &mut Transform)>,
+        Query<(&PieceComponent, &mut Transform, &mut TextureAtlasSprite)>,
+        Query<&mut Transform, Or<(With<SelectingStartSquare>, With<SelectingEndSquare>)>>,
+        Query<&mut Transform, With<Camera>>,
+    )>,
+    mut panel_q: Query<(&PanelItem, &mut Style)>,
+    mut text_q: Query<(&ScaledText, &mut Text)>,
+) {
+    if !layout.is_changed() && !orientation.is_changed() {
+        return;
+    }
+    let piece_size = layout.piece_size();
+
+    for (mut square, mut transform) in set.p0().iter_mut() {
+        let position = square_position(square.chess_sq, *orientation, piece_size);
+        square.position = position;
+        square.piece_size = piece_size;
+        transform.translation = position.extend(transform.translation.z);
+        transform.scale = Vec3::new(piece_size, piece_size, 1.);
+    }
+
+    // The selection holds copies of the squares, kept so a pending promotion stays pending
+    let mut selected = selected_q.single_mut();
+    let selected = &mut *selected;
+    for square in [&mut selected.start, &mut selected.end]
+        .into_iter()
+        .flatten()
+    {
+        square.position = square_position(square.chess_sq, *orientation, piece_size);
+        square.piece_size = piece_size;
+    }
+    // The pawn waits on its destination while the piece is picked
+    let picking = match (&selected.start, &selected.end, selected.promotion) {
+        (Some(start), Some(end), Some(Promotion::Picking)) => Some((start.chess_sq, end.position)),
+        _ => None,
+    };
+
+    // Sliding pieces land at once, see `finish_animations`
+    for (piece, mut transform, mut sprite) in set.p1().iter_mut() {
+        let position = match picking {
+            Some((start, end)) if piece.square == start => end,
+            _ => square_position(piece.square, *orientation, piece_size),
+        };
+        transform.translation = position.extend(900.);
+        sprite.custom_size = Some(Vec2::splat(piece_size - 10.));
+    }
+
+    for mut transform in set.p2().iter_mut() {
+        transform.scale = Vec3::new(piece_size, piece_size, 1.);
+    }
+
+    for mut transform in set.p3().iter_mut() {
+        transform.translation.x = layout.camera_offset();
+    }
+
+    for (item, mut style) in panel_q.iter_mut() {
+        let (position, size) = layout.place(item, *orientation);
+        style.position = position;
+        style.size = size;
+    }
+
+    for (scaled, mut text) in text_q.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.font_size = scaled.0 * layout.scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoardLayout, PanelAnchor, PanelItem, PanelSlot};
+    use crate::{BoardOrientation, HEIGHT, RESOLUTION, RIGHT_UI};
+    use bevy::prelude::*;
+
+    /// The top and bottom of `item` in window pixels.
+    fn span(layout: &BoardLayout, item: &PanelItem) -> (f32, f32) {
+        match layout.place(item, BoardOrientation::White) {
+            (
+                UiRect {
+                    top: Val::Px(top), ..
+                },
+                Size {
+                    height: Val::Px(height),
+                    ..
+                },
+            ) => (top, top + height),
+            place => panic!("{:?} is not placed in pixels: {:?}", item, place),
+        }
+    }
+
+    #[test]
+    fn stacks_the_panel_between_the_clocks() {
+        let countdown = |color| PanelItem {
+            anchor: PanelAnchor::Countdown(color),
+            height: 40.,
+        };
+        for (width, height) in [(HEIGHT * RESOLUTION + RIGHT_UI, HEIGHT), (1600., 900.)] {
+            let layout = BoardLayout::new(width, height);
+            let (_, mut above) = span(&layout, &countdown(chess::Color::Black));
+            for slot in PanelSlot::STACK {
+                let (top, bottom) = span(&layout, &PanelItem::stacked(slot));
+                assert!(top >= above, "{:?} overlaps the widget above it", slot);
+                above = bottom;
+            }
+            let (below, _) = span(&layout, &countdown(chess::Color::White));
+            assert!(above <= below, "the stack runs into White's clock");
+        }
+    }
+}
@@ -1,37 +1,77 @@
+mod coordinates;
 mod debug;
+mod engine;
+mod fen;
 mod frame_per_second;
+mod game;
+#[cfg(test)]
+mod harness;
+mod highlights;
+mod history;
+mod layout;
+mod move_hints;
+mod pgn;
+mod pgn_import;
+mod pieces;
+mod replay;
+mod san;
+mod stdin_moves;
+mod takeback;
+mod theme;
+mod time_control;
+mod uci;
 
 use bevy::{
-    input::mouse::MouseButtonInput, prelude::*, time::Stopwatch, utils::Duration,
+    input::mouse::MouseButtonInput, prelude::*, utils::Duration, window::FileDragAndDrop,
     winit::WinitSettings,
 };
+use coordinates::CoordinatesPlugin;
 use debug::DebugPlugin;
+use engine::{EnginePlugin, EngineSettings};
+use fen::{parse_fen, read_fen_file, FenError, Position};
 use frame_per_second::FPSDiagPlugin;
+use game::{Game, MoveError};
+use highlights::HighlightsPlugin;
+use history::MoveHistoryPlugin;
+use layout::{BoardLayout, LayoutPlugin, PanelAnchor, PanelItem, PanelSlot, ScaledText};
+use move_hints::MoveHintsPlugin;
+use pgn::{PgnExportPlugin, PgnOutput};
+use pieces::{PiecesPlugin, Slide};
+use replay::{OpenPgn, Replay, ReplayPlugin};
+use stdin_moves::StdinMovesPlugin;
+use takeback::{TakebackPlugin, TakebackSettings, Takebacks};
+use theme::{ThemeFonts, ThemePlugin, Themes};
+use time_control::TimeControl;
+use uci::UciPlugin;
 
 const CLEAR: Color = Color::rgb(0.1, 0.1, 0.1);
 const RESOLUTION: f32 = 1.;
 const HEIGHT: f32 = 600.0;
 const RIGHT_UI: f32 = 100.0;
-const WHITE_SQUARE_COLOR: Color = Color::rgb(240. / 255., 217. / 255., 181. / 255.);
-const BLACK_SQUARE_COLOR: Color = Color::rgb(181. / 255., 136. / 255., 99. / 255.);
-const START_COLOR: Color = Color::rgb(0.35, 0.75, 0.35);
-const END_COLOR: Color = Color::rgb(0.8, 0.75, 0.35);
+const DRAG_Z: f32 = 950.;
+const PROMOTION_COLOR: Color = Color::rgb(0.85, 0.85, 0.85);
+const PROMOTION_PIECES: [chess::Piece; 4] = [
+    chess::Piece::Queen,
+    chess::Piece::Rook,
+    chess::Piece::Bishop,
+    chess::Piece::Knight,
+];
 
 const FONT_SIZE: f32 = 32.0;
 const FONT_COLOR: Color = Color::WHITE;
+const RESULT_FONT_SIZE: f32 = 16.0;
+const STATUS_FONT_SIZE: f32 = 12.0;
+const TIME_CONTROL_FONT_SIZE: f32 = 18.0;
+const COPY_FEN_FONT_SIZE: f32 = 18.0;
 
-const GAME_DURATION: u64 = 60 * 10;
+#[derive(Component)]
+struct StartButton;
 
 #[derive(Component)]
-pub struct GameState {
-    duration: Duration,
-    white_watch: Stopwatch,
-    black_watch: Stopwatch,
-    is_running: bool,
-}
+struct TimeControlButton;
 
 #[derive(Component)]
-struct StartButton;
+struct TimeControlText;
 
 #[derive(Component)]
 struct WhiteCountdown;
@@ -39,19 +79,46 @@ struct WhiteCountdown;
 #[derive(Component)]
 struct BlackCountdown;
 
-#[derive(Debug, Component)]
-struct BoardComponent(chess::Board);
+#[derive(Component)]
+struct ResultBanner;
 
-#[derive(Debug, Component)]
+#[derive(Component)]
+struct StatusText;
+
+#[derive(Component)]
+struct CopyFenButton;
+
+/// A message for the status line of the side panel.
+struct StatusMessage(String);
+
+/// Replaces the game with a new position.
+struct LoadPosition(Position);
+
+/// A move `handle_chess_move` tried to play, and whether the game took it.
+struct MoveAttempt {
+    m: chess::ChessMove,
+    result: Result<(), MoveError>,
+}
+
+/// Where "Copy FEN" puts the current position.
+enum FenOutput {
+    Clipboard,
+    Stdout,
+    File(std::path::PathBuf),
+}
+
+/// The piece a sprite shows and the square it stands on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
 struct PieceComponent {
-    position: Vec2,
+    square: chess::Square,
+    piece: chess::Piece,
+    color: chess::Color,
 }
 
 #[derive(Debug, Clone, Component)]
 struct SquareComponent {
     chess_sq: chess::Square,
     position: Vec2,
-    bottom_left_coord: Vec2,
     piece_size: f32,
 }
 
@@ -59,8 +126,8 @@ struct SquareComponent {
 struct SelectingSquares {
     start: Option<SquareComponent>,
     end: Option<SquareComponent>,
-    en_passant: Option<SquareComponent>, // The en_passant pawn to be taken
-    castle: Option<SquareComponent>,     // The castled rook
+    promotion: Option<Promotion>,
+    dragging: Option<Entity>, // The piece sprite following the cursor
 }
 
 impl Default for SelectingSquares {
@@ -68,8 +135,8 @@ impl Default for SelectingSquares {
         Self {
             start: None,
             end: None,
-            en_passant: None,
-            castle: None,
+            promotion: None,
+            dragging: None,
         }
     }
 }
@@ -78,8 +145,42 @@ impl SelectingSquares {
     fn reset(&mut self) {
         self.start = None;
         self.end = None;
-        self.en_passant = None;
-        self.castle = None;
+        self.promotion = None;
+    }
+}
+
+/// Progress of picking the piece for a pawn reaching the last rank.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Promotion {
+    Picking,
+    Chosen(chess::Piece),
+    Cancelled,
+}
+
+#[derive(Debug, Component)]
+struct PromotionChoice;
+
+/// The side shown at the bottom of the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BoardOrientation {
+    White,
+    Black,
+}
+
+impl BoardOrientation {
+    /// The side playing from the bottom edge.
+    fn bottom(self) -> chess::Color {
+        match self {
+            Self::White => chess::Color::White,
+            Self::Black => chess::Color::Black,
+        }
+    }
+
+    fn flipped(self) -> Self {
+        match self {
+            Self::White => Self::Black,
+            Self::Black => Self::White,
+        }
     }
 }
 
@@ -126,55 +227,127 @@ impl PieceSprite {
     }
 }
 
+/// The starting position from `--fen <FEN>` or `--fen-file <path>`.
+fn start_position_from_args() -> Result<Position, FenError> {
+    let args: Vec<String> = std::env::args().collect();
+    for (i, arg) in args.iter().enumerate() {
+        let value = || {
+            args.get(i + 1).cloned().ok_or_else(|| FenError {
+                fen: String::new(),
+                reason: format!("{} needs a value", arg),
+            })
+        };
+        match arg.as_str() {
+            "--fen" => return parse_fen(&value()?),
+            "--fen-file" => return read_fen_file(std::path::Path::new(&value()?)),
+            _ => {}
+        }
+    }
+    Ok(Position::default())
+}
+
+/// The value following the command line option `name`.
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != name);
+    args.nth(1)
+}
+
+/// The FEN destination from `--fen-out <path>`, where `-` is stdout.
+fn fen_output_from_args() -> FenOutput {
+    match arg_value("--fen-out") {
+        Some(path) if path == "-" => FenOutput::Stdout,
+        Some(path) => FenOutput::File(path.into()),
+        None => FenOutput::Clipboard,
+    }
+}
+
 fn main() {
+    let start_position = match start_position_from_args() {
+        Ok(position) => position,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(2);
+        }
+    };
+    let orientation = if std::env::args().any(|arg| arg == "--black") {
+        BoardOrientation::Black
+    } else {
+        BoardOrientation::White
+    };
+
+    let layout = BoardLayout::new(HEIGHT * RESOLUTION + RIGHT_UI, HEIGHT);
+
     App::new()
         .add_startup_system(spawn_camera)
-        .add_startup_system_to_stage(StartupStage::PreStartup, load_chess_piece_sprites)
-        .add_startup_system_to_stage(StartupStage::Startup, spawn_pieces)
+        .add_startup_system_to_stage(StartupStage::Startup, spawn_board)
         .add_startup_system_to_stage(StartupStage::Startup, spawn_countdowns)
         .insert_resource(ClearColor(CLEAR))
+        .insert_resource(orientation)
+        .insert_resource(layout)
+        .insert_resource(Game::new(start_position, TimeControl::default()))
+        .insert_resource(fen_output_from_args())
+        .insert_resource(TakebackSettings {
+            confirm: std::env::args().any(|arg| arg == "--confirm-takebacks"),
+        })
+        .insert_resource(
+            arg_value("--pgn-out")
+                .map_or_else(PgnOutput::default, |path| PgnOutput::File(path.into())),
+        )
+        .add_event::<LoadPosition>()
+        .add_event::<StatusMessage>()
+        .add_event::<MoveAttempt>()
         .insert_resource(WinitSettings::desktop_app())
         .insert_resource(WindowDescriptor {
-            width: HEIGHT * RESOLUTION + RIGHT_UI,
-            height: HEIGHT,
+            width: layout.window.x,
+            height: layout.window.y,
             title: "Bevy chess by Chop Tr".to_string(),
-            resizable: false,
+            resizable: true,
             ..Default::default()
         })
         .add_plugins(DefaultPlugins)
+        .add_plugin(ThemePlugin)
         .add_plugin(DebugPlugin)
         .add_plugin(FPSDiagPlugin)
+        .add_plugin(PiecesPlugin)
+        .add_plugin(HighlightsPlugin)
+        .add_plugin(CoordinatesPlugin)
+        .add_plugin(MoveHintsPlugin)
+        .add_plugin(LayoutPlugin)
+        .add_plugin(MoveHistoryPlugin)
+        .add_plugin(PgnExportPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(TakebackPlugin)
+        .add_plugin(EnginePlugin)
+        .add_plugin(UciPlugin)
+        .add_plugin(StdinMovesPlugin)
         .add_system(click_start)
+        .add_system(click_time_control)
         .add_system(timer_tick)
         .add_system(timer_display)
+        .add_system(result_display)
         .add_system(mouse_select_system)
         .add_system(highlight_selected)
         .add_system(handle_chess_move)
+        .add_system(promotion_picker)
+        .add_system(flip_board)
+        .add_system(paste_fen)
+        .add_system(copy_fen)
+        .add_system(drop_file)
+        .add_system(load_position)
+        .add_system(status_display)
         .run();
 }
 
-fn spawn_camera(mut commands: Commands) {
+fn spawn_camera(mut commands: Commands, layout: Res<BoardLayout>) {
     commands.spawn_bundle(Camera2dBundle {
         transform: Transform {
-            translation: Vec3::new(RIGHT_UI / 2., 0., 999.9),
+            translation: Vec3::new(layout.camera_offset(), 0., 999.9),
             ..default()
         },
         ..default()
     });
 }
 
-fn load_chess_piece_sprites(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-) {
-    let image = asset_server.load("chess-pieces.png");
-    let texture_atlas = TextureAtlas::from_grid(image, Vec2::new(106., 106.), 6, 2);
-    let texture_atlas_handle = texture_atlases.add(texture_atlas);
-
-    commands.insert_resource(ChessPieceSprites(texture_atlas_handle));
-}
-
 fn translate_square_to_xy(sq: chess::Square) -> (usize, usize) {
     let sq_index = sq.to_index();
     let x = sq_index % 8;
@@ -182,6 +355,13 @@ fn translate_square_to_xy(sq: chess::Square) -> (usize, usize) {
     (x, y)
 }
 
+fn orient_xy((x, y): (usize, usize), orientation: BoardOrientation) -> (usize, usize) {
+    match orientation {
+        BoardOrientation::White => (x, y),
+        BoardOrientation::Black => (7 - x, 7 - y),
+    }
+}
+
 fn translate_xy_to_center_coord(x: usize, y: usize) -> (i32, i32) {
     let delta = 4;
     let new_x = x as i32 - delta;
@@ -193,13 +373,38 @@ fn square_center_vector_from_coord(x: f32, y: f32, piece_size: f32, half_piece:
     Vec2::new(x * piece_size + half_piece, y * piece_size + half_piece)
 }
 
-fn translate_center_coord_vec_to_bottom_left_vec(v: Vec2, window: &Window) -> Vec2 {
-    Vec2::new(v.x + (window.width() / 2.), v.y + (window.height() / 2.))
+/// The centre of `sq` in world coordinates.
+fn square_position(sq: chess::Square, orientation: BoardOrientation, piece_size: f32) -> Vec2 {
+    let (x, y) = orient_xy(translate_square_to_xy(sq), orientation);
+    let (x, y) = translate_xy_to_center_coord(x, y);
+    square_center_vector_from_coord(x as f32, y as f32, piece_size, piece_size / 2.)
+}
+
+/// The squares of the promotion picker, from the promotion square towards the board centre.
+fn promotion_choices(
+    end: chess::Square,
+    color: chess::Color,
+) -> Vec<(chess::Square, chess::Piece)> {
+    let mut next = Some(end);
+    PROMOTION_PIECES
+        .iter()
+        .filter_map(|&piece| {
+            let sq = next?;
+            next = if color == chess::Color::White {
+                sq.down()
+            } else {
+                sq.up()
+            };
+            Some((sq, piece))
+        })
+        .collect()
 }
 
+/// `mm:ss`, or `h:mm:ss` from an hour on.
 fn format_duration(dur: &Duration) -> String {
     let seconds = dur.as_secs() % 60;
     let minutes = (dur.as_secs() / 60) % 60;
+    let hours = dur.as_secs() / 3600;
     let padding_zero = |n: u64| {
         if n < 10 {
             format!("0{n}")
@@ -207,18 +412,37 @@ fn format_duration(dur: &Duration) -> String {
             n.to_string()
         }
     };
-    format!("{}:{}", padding_zero(minutes), padding_zero(seconds))
+    if hours > 0 {
+        format!(
+            "{}:{}:{}",
+            hours,
+            padding_zero(minutes),
+            padding_zero(seconds)
+        )
+    } else {
+        format!("{}:{}", padding_zero(minutes), padding_zero(seconds))
+    }
 }
 
-fn spawn_countdowns(windows: Res<Windows>, mut commands: Commands, asset_server: Res<AssetServer>) {
-    let window = windows.get_primary().unwrap();
-    let font = asset_server.load("fonts/FiraMono-Bold.ttf");
-    let text_style = |position: UiRect<Val>| {
-        TextBundle::from_section(
+fn spawn_countdowns(
+    mut commands: Commands,
+    fonts: Res<ThemeFonts>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    game: Res<Game>,
+) {
+    let font = fonts.font.clone();
+    let text_style = |color: chess::Color| {
+        let item = PanelItem {
+            anchor: PanelAnchor::Countdown(color),
+            height: 40.,
+        };
+        let (position, size) = layout.place(&item, *orientation);
+        let bundle = TextBundle::from_section(
             format_duration(&Duration::from_secs(0)),
             TextStyle {
                 font: font.clone(),
-                font_size: FONT_SIZE,
+                font_size: FONT_SIZE * layout.scale,
                 color: FONT_COLOR,
             },
         )
@@ -226,70 +450,161 @@ fn spawn_countdowns(windows: Res<Windows>, mut commands: Commands, asset_server:
             align_self: AlignSelf::FlexEnd,
             position_type: PositionType::Absolute,
             position,
-            size: Size {
-                width: Val::Px(RIGHT_UI),
-                height: Val::Px(40.),
-                ..default()
-            },
+            size,
             ..default()
-        })
+        });
+        (bundle, item)
+    };
+    let button_style = |item: &PanelItem| {
+        let (position, size) = layout.place(item, *orientation);
+        Style {
+            size,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            position_type: PositionType::Absolute,
+            position,
+            ..default()
+        }
     };
 
-    commands.spawn().insert(GameState {
-        duration: Duration::from_secs(GAME_DURATION),
-        white_watch: Stopwatch::new(),
-        black_watch: Stopwatch::new(),
-        is_running: false,
-    });
+    let time_control_name = game.time_control().name.clone();
 
+    let item = PanelItem::stacked(PanelSlot::CopyFen);
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: button_style(&item),
+            color: Color::rgb(0.15, 0.15, 0.15).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    "Copy FEN",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: COPY_FEN_FONT_SIZE * layout.scale,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                ))
+                .insert(ScaledText(COPY_FEN_FONT_SIZE));
+        })
+        .insert(Name::new("CopyFenButton"))
+        .insert(item)
+        .insert(CopyFenButton);
+
+    let item = PanelItem::stacked(PanelSlot::TimeControl);
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: button_style(&item),
+            color: Color::rgb(0.15, 0.15, 0.15).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    time_control_name,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: TIME_CONTROL_FONT_SIZE * layout.scale,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                ))
+                .insert(ScaledText(TIME_CONTROL_FONT_SIZE))
+                .insert(TimeControlText);
+        })
+        .insert(Name::new("TimeControlButton"))
+        .insert(item)
+        .insert(TimeControlButton);
+
+    let item = PanelItem::stacked(PanelSlot::Start);
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(100.), Val::Px(65.)),
                 margin: UiRect::all(Val::Auto),
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                position_type: PositionType::Absolute,
-                position: UiRect {
-                    right: Val::Px(0.),
-                    top: Val::Px((window.height() - 65.) / 2.),
-                    ..default()
-                },
-                ..default()
+                ..button_style(&item)
             },
             color: Color::rgb(0.15, 0.15, 0.15).into(),
             ..default()
         })
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle::from_section(
-                "Start",
-                TextStyle {
-                    font: font.clone(),
-                    font_size: FONT_SIZE,
-                    color: Color::rgb(0.9, 0.9, 0.9),
-                },
-            ));
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    "Start",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: FONT_SIZE * layout.scale,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                ))
+                .insert(ScaledText(FONT_SIZE));
         })
         .insert(Name::new("StartButton"))
+        .insert(item)
         .insert(StartButton);
 
+    let (bundle, item) = text_style(chess::Color::White);
     commands
-        .spawn_bundle(text_style(UiRect {
-            bottom: Val::Px(5.0),
-            right: Val::Px(0.),
-            ..default()
-        }))
+        .spawn_bundle(bundle)
         .insert(Name::new("WhiteCountdown"))
+        .insert(item)
+        .insert(ScaledText(FONT_SIZE))
         .insert(WhiteCountdown);
 
+    let (bundle, item) = text_style(chess::Color::Black);
     commands
-        .spawn_bundle(text_style(UiRect {
-            top: Val::Px(5.0),
-            right: Val::Px(0.),
-            ..default()
-        }))
+        .spawn_bundle(bundle)
         .insert(Name::new("BlackCountdown"))
+        .insert(item)
+        .insert(ScaledText(FONT_SIZE))
         .insert(BlackCountdown);
+
+    let item = PanelItem::stacked(PanelSlot::Result);
+    let (position, size) = layout.place(&item, *orientation);
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: RESULT_FONT_SIZE * layout.scale,
+                    color: FONT_COLOR,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position,
+                size,
+                ..default()
+            }),
+        )
+        .insert(Name::new("ResultBanner"))
+        .insert(item)
+        .insert(ScaledText(RESULT_FONT_SIZE))
+        .insert(ResultBanner);
+
+    let item = PanelItem::stacked(PanelSlot::Status);
+    let (position, size) = layout.place(&item, *orientation);
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: STATUS_FONT_SIZE * layout.scale,
+                    color: FONT_COLOR,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position,
+                size,
+                ..default()
+            }),
+        )
+        .insert(Name::new("StatusText"))
+        .insert(item)
+        .insert(ScaledText(STATUS_FONT_SIZE))
+        .insert(StatusText);
 }
 
 fn click_start(
@@ -298,7 +613,8 @@ fn click_start(
         (Entity, &Interaction, &Children),
         (Changed<Interaction>, With<StartButton>),
     >,
-    mut game_q: Query<&mut GameState>,
+    time_control_q: Query<Entity, With<TimeControlButton>>,
+    mut game: ResMut<Game>,
 ) {
     for (entity, interaction, children) in &mut interaction_query {
         if *interaction == Interaction::Clicked {
@@ -306,52 +622,54 @@ fn click_start(
                 commands.entity(*child).despawn();
             });
             commands.entity(entity).despawn();
-            let mut game = game_q.single_mut();
-            game.is_running = true;
+            // The time control is fixed once the game starts
+            for button in time_control_q.iter() {
+                commands.entity(button).despawn_recursive();
+            }
+            game.start();
         }
     }
 }
 
-fn spawn_pieces(mut commands: Commands, pieces: Res<ChessPieceSprites>, windows: Res<Windows>) {
-    let window = windows.get_primary().unwrap();
-    let piece_size = window.height() / 8.;
-    let half_piece = piece_size / 2.;
-
-    let piece_to_sprite = |piece: PieceSprite, vs: Vec2| -> SpriteSheetBundle {
-        let mut sprite = TextureAtlasSprite::new(piece as usize);
-        sprite.custom_size = Some(Vec2::splat(piece_size - 10.));
-
-        SpriteSheetBundle {
-            sprite,
-            texture_atlas: pieces.0.clone(),
-            transform: Transform {
-                translation: Vec3::new(vs.x, vs.y, 900.),
-                scale: Vec3::new(0.9, 0.9, 1.),
-                ..default()
-            },
-            ..default()
+fn click_time_control(
+    interaction_q: Query<&Interaction, (Changed<Interaction>, With<TimeControlButton>)>,
+    mut text_q: Query<&mut Text, With<TimeControlText>>,
+    mut game: ResMut<Game>,
+) {
+    for interaction in interaction_q.iter() {
+        if *interaction == Interaction::Clicked {
+            let presets = TimeControl::presets();
+            let next = presets
+                .iter()
+                .position(|preset| preset == game.time_control())
+                .map_or(0, |i| (i + 1) % presets.len());
+            game.set_time_control(presets[next].clone());
+            for mut text in text_q.iter_mut() {
+                text.sections[0].value = game.time_control().name.clone();
+            }
         }
-    };
+    }
+}
 
-    let board = chess::Board::default();
-    commands.spawn().insert(BoardComponent(board));
+/// Spawns the squares and the selection highlights; `sync_pieces` adds the pieces.
+fn spawn_board(
+    mut commands: Commands,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    themes: Res<Themes>,
+) {
+    let piece_size = layout.piece_size();
+    let theme = themes.current();
 
     for &sq in chess::ALL_SQUARES.iter() {
-        let (x, y) = translate_square_to_xy(sq);
-        let (x, y) = translate_xy_to_center_coord(x, y);
-        let vs = square_center_vector_from_coord(x as f32, y as f32, piece_size, half_piece);
-        let color = if (x + y + 1) % 2 == 0 {
-            BLACK_SQUARE_COLOR
-        } else {
-            WHITE_SQUARE_COLOR
-        };
+        let vs = square_position(sq, *orientation, piece_size);
+        let color = theme.square_color(sq);
         commands
             .spawn()
             .insert(Name::new(format!("Square {}", sq)))
             .insert(SquareComponent {
                 chess_sq: sq,
                 position: Vec2::new(vs.x, vs.y),
-                bottom_left_coord: translate_center_coord_vec_to_bottom_left_vec(vs, window),
                 piece_size,
             })
             .insert_bundle(SpriteBundle {
@@ -363,19 +681,6 @@ fn spawn_pieces(mut commands: Commands, pieces: Res<ChessPieceSprites>, windows:
                 },
                 ..default()
             });
-
-        let piece = board.piece_on(sq);
-        if piece.is_none() {
-            continue;
-        }
-        let piece = piece.unwrap();
-        let color = board.color_on(sq).unwrap();
-
-        commands
-            .spawn()
-            .insert(Name::new(piece.to_string(color)))
-            .insert(PieceComponent { position: vs })
-            .insert_bundle(piece_to_sprite(PieceSprite::from_chess(piece, color), vs));
     }
 
     commands
@@ -396,129 +701,225 @@ fn spawn_pieces(mut commands: Commands, pieces: Res<ChessPieceSprites>, windows:
     commands
         .spawn()
         .insert(SelectingStartSquare)
-        .insert_bundle(spawn_selecting_square(START_COLOR));
+        .insert_bundle(spawn_selecting_square(theme.selection_start));
 
     commands
         .spawn()
         .insert(SelectingEndSquare)
-        .insert_bundle(spawn_selecting_square(END_COLOR));
+        .insert_bundle(spawn_selecting_square(theme.selection_end));
 }
 
-fn timer_tick(
-    time: Res<Time>,
-    board_q: Query<&mut BoardComponent>,
-    mut game_q: Query<&mut GameState>,
-) {
-    let board = board_q.single();
-    let mut game = game_q.single_mut();
-
-    if !game.is_running {
-        return;
-    }
-
-    if board.0.side_to_move() == chess::Color::White {
-        game.white_watch.tick(time.delta());
-    } else {
-        game.black_watch.tick(time.delta());
+fn timer_tick(time: Res<Time>, mut game: ResMut<Game>) {
+    // Only touch the game while the clocks run, so it reads as changed after moves
+    if game.is_running() {
+        game.tick(time.delta());
     }
 }
 
 fn timer_display(
-    game_q: Query<&GameState>,
+    game: Res<Game>,
     mut set: ParamSet<(
         Query<&mut Text, With<WhiteCountdown>>,
         Query<&mut Text, With<BlackCountdown>>,
     )>,
 ) {
-    let game = game_q.single();
     for mut text in set.p0().iter_mut() {
-        text.sections[0].value = format_duration(
-            &game
-                .duration
-                .checked_sub(game.white_watch.elapsed())
-                .unwrap_or_else(|| Duration::from_secs(0)),
-        );
+        text.sections[0].value = format_duration(&game.remaining(chess::Color::White));
     }
 
     for mut text in set.p1().iter_mut() {
-        text.sections[0].value = format_duration(
-            &game
-                .duration
-                .checked_sub(game.black_watch.elapsed())
-                .unwrap_or_else(|| Duration::from_secs(0)),
-        );
+        text.sections[0].value = format_duration(&game.remaining(chess::Color::Black));
+    }
+}
+
+fn result_display(game: Res<Game>, mut banner_q: Query<&mut Text, With<ResultBanner>>) {
+    if !game.is_changed() {
+        return;
+    }
+    let banner = game
+        .result()
+        .map_or_else(String::new, |result| result.to_string());
+    for mut text in banner_q.iter_mut() {
+        if text.sections[0].value != banner {
+            text.sections[0].value = banner.clone();
+        }
     }
 }
 
+fn square_at(square_query: &Query<&SquareComponent>, position: Vec2) -> Option<SquareComponent> {
+    square_query
+        .iter()
+        .find(|&sq| {
+            let half_piece = sq.piece_size / 2.;
+            let (bl_x, bl_y) = (sq.position.x - half_piece, sq.position.y - half_piece);
+            let (tr_x, tr_y) = (bl_x + sq.piece_size, bl_y + sq.piece_size);
+            let (pos_x, pos_y) = (position.x, position.y);
+            bl_x < pos_x && bl_y < pos_y && tr_x > pos_x && tr_y > pos_y
+        })
+        .cloned()
+}
+
+/// Sets `end` as the destination of the selected piece, or re-selects / deselects when it is
+/// not a legal destination. Returns whether a move was chosen.
+fn choose_destination(selected: &mut SelectingSquares, game: &Game, end: SquareComponent) -> bool {
+    let start = match selected.start.as_ref() {
+        Some(start) => start.chess_sq,
+        None => return false,
+    };
+    if !game.legal_destinations(start).contains(&end.chess_sq) {
+        // Re-select another piece of the side to move, otherwise deselect
+        let board = game.board();
+        let own_piece = board.color_on(end.chess_sq) == Some(board.side_to_move());
+        selected.reset();
+        if own_piece && end.chess_sq != start {
+            selected.start = Some(end);
+        }
+        return false;
+    }
+    selected.end = Some(end);
+    true
+}
+
+/// Selects `m` as if it had been clicked, for `handle_chess_move` to play. Returns whether it
+/// is a legal move.
+fn select_move(
+    selected: &mut SelectingSquares,
+    game: &Game,
+    square_query: &Query<&SquareComponent>,
+    m: chess::ChessMove,
+) -> bool {
+    let square = |sq| square_query.iter().find(|&s| s.chess_sq == sq).cloned();
+    selected.reset();
+    selected.start = square(m.get_source());
+    let end = match square(m.get_dest()) {
+        Some(end) => end,
+        None => return false,
+    };
+    if !choose_destination(selected, game, end) {
+        selected.reset();
+        return false;
+    }
+    if let Some(piece) = m.get_promotion() {
+        selected.promotion = Some(Promotion::Chosen(piece));
+    }
+    true
+}
+
+/// Returns the dragged piece sprite to its square.
+fn snap_back(
+    selected: &mut SelectingSquares,
+    square_query: &Query<&SquareComponent>,
+    piece_q: &mut Query<(Entity, &PieceComponent, &mut Transform)>,
+) {
+    if let Some((_, piece, mut transform)) = selected
+        .dragging
+        .take()
+        .and_then(|entity| piece_q.get_mut(entity).ok())
+    {
+        if let Some(square) = square_query.iter().find(|sq| sq.chess_sq == piece.square) {
+            transform.translation = square.position.extend(900.);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn mouse_select_system(
-    board_q: Query<&mut BoardComponent>,
+    game: Res<Game>,
     windows: Res<Windows>,
+    layout: Res<BoardLayout>,
     square_query: Query<&SquareComponent>,
     mut mousebtn_evr: EventReader<MouseButtonInput>,
     mut selected_query: Query<&mut SelectingSquares>,
+    mut piece_q: Query<(Entity, &PieceComponent, &mut Transform)>,
+    replay: Res<Replay>,
+    engine: Res<EngineSettings>,
 ) {
     use bevy::input::ButtonState;
+    let mut selected = selected_query.single_mut();
+    let board = game.board();
+    // No input while the game is over, replayed or the engine's to move
+    if game.result().is_some() || replay.is_active() || engine.plays(board) {
+        if selected.dragging.is_some() {
+            snap_back(&mut selected, &square_query, &mut piece_q);
+        }
+        return;
+    }
     let window = windows.get_primary().unwrap();
     let position = window.cursor_position();
     if position.is_none() {
         return;
     }
 
-    let position = position.unwrap();
-    let board = board_q.single();
+    let position = layout.cursor_to_world(position.unwrap());
 
     for ev in mousebtn_evr.iter() {
-        if ev.state == ButtonState::Pressed {
-            let found_selected = square_query
-                .iter()
-                .find(|&sq| {
-                    let half_piece = sq.piece_size / 2.;
-                    let (bl_x, bl_y) = (
-                        sq.bottom_left_coord.x - half_piece,
-                        sq.bottom_left_coord.y - half_piece,
-                    );
-                    let (tr_x, tr_y) = (bl_x + sq.piece_size, bl_y + sq.piece_size);
-                    let (pos_x, pos_y) = (position.x, position.y);
-                    let x_padding = RIGHT_UI / 2.;
-                    bl_x < pos_x + x_padding
-                        && bl_y < pos_y
-                        && tr_x > pos_x + x_padding
-                        && tr_y > pos_y
-                })
-                .map(|s| s.clone());
-            if found_selected.is_none() {
+        let found_selected = square_at(&square_query, position);
+        if ev.state == ButtonState::Released {
+            if selected.dragging.is_none() {
                 continue;
             }
-            let found_selected = found_selected.unwrap();
-            let mut selected = selected_query.single_mut();
-            if selected.start.is_none() {
-                if board.0.piece_on(found_selected.chess_sq).is_some() {
-                    let color = board.0.color_on(found_selected.chess_sq).unwrap();
-                    if color == board.0.side_to_move() {
-                        selected.start = Some(found_selected);
-                    }
+            // Dropping back on the origin keeps the piece selected for click-click
+            let start = selected.start.as_ref().map(|start| start.chess_sq);
+            let chosen = match found_selected {
+                Some(found) if Some(found.chess_sq) != start => {
+                    choose_destination(&mut selected, &game, found)
                 }
+                _ => false,
+            };
+            if chosen {
+                // The piece slides on from where it was dropped
+                selected.dragging = None;
             } else {
-                if let Some(en_passant) = board.0.en_passant() {
-                    let en_passant_target = if board.0.side_to_move() == chess::Color::White {
-                        found_selected.chess_sq.down()
-                    } else {
-                        found_selected.chess_sq.up()
-                    };
-                    if let Some(en_passant_target) = en_passant_target {
-                        if en_passant == en_passant_target {
-                            selected.en_passant = square_query
-                                .iter()
-                                .find(|&sq| sq.chess_sq == en_passant)
-                                .map(|sq| sq.clone());
-                        }
-                    }
+                snap_back(&mut selected, &square_query, &mut piece_q);
+                if found_selected.map(|found| found.chess_sq) != start {
+                    selected.reset();
                 }
-                // if found_selected.position board.0.legal(m)
-                selected.end = Some(found_selected);
             }
+            continue;
+        }
+
+        if selected.promotion == Some(Promotion::Picking) {
+            let choice = found_selected.as_ref().and_then(|found| {
+                selected.end.as_ref().and_then(|end| {
+                    promotion_choices(end.chess_sq, board.side_to_move())
+                        .into_iter()
+                        .find(|&(sq, _)| sq == found.chess_sq)
+                })
+            });
+            selected.promotion = Some(match choice {
+                Some((_, piece)) => Promotion::Chosen(piece),
+                None => Promotion::Cancelled,
+            });
+            continue;
+        }
+        if found_selected.is_none() {
+            continue;
+        }
+        let found_selected = found_selected.unwrap();
+        let is_start = selected
+            .start
+            .as_ref()
+            .map_or(false, |start| start.chess_sq == found_selected.chess_sq);
+        if selected.start.is_none() || is_start {
+            if board.color_on(found_selected.chess_sq) == Some(board.side_to_move()) {
+                selected.start = Some(found_selected);
+            }
+        } else if choose_destination(&mut selected, &game, found_selected) {
+            continue;
+        }
+
+        // Pick up the selected piece, dragging it until the button is released
+        if let Some(start) = selected.start.as_ref() {
+            selected.dragging = piece_q
+                .iter()
+                .find(|(_, piece, _)| piece.square == start.chess_sq)
+                .map(|(entity, _, _)| entity);
         }
     }
+
+    if let Some((_, _, mut transform)) = selected.dragging.and_then(|e| piece_q.get_mut(e).ok()) {
+        transform.translation = position.extend(DRAG_Z);
+    }
 }
 
 fn highlight_selected(
@@ -551,47 +952,330 @@ fn highlight_selected(
 }
 
 fn handle_chess_move(
-    mut commands: Commands,
-    mut board_q: Query<&mut BoardComponent>,
+    mut game: ResMut<Game>,
+    mut takebacks: ResMut<Takebacks>,
     mut selected_q: Query<&mut SelectingSquares>,
-    mut piece_q: Query<(Entity, &mut PieceComponent, &mut Transform), With<PieceComponent>>,
+    mut piece_q: Query<(&PieceComponent, &mut Transform)>,
+    slide_q: Query<(), With<Slide>>,
+    mut move_evw: EventWriter<MoveAttempt>,
 ) {
     let mut selected = selected_q.single_mut();
-    let en_passant = selected.as_ref().en_passant.as_ref();
-    if let (Some(start), Some(end)) = (selected.start.as_ref(), selected.end.as_ref()) {
-        let mut board = board_q.single_mut();
-        let m = chess::ChessMove::new(start.chess_sq, end.chess_sq, None);
-        if board.0.legal(m) {
-            board.0 = board.0.make_move_new(m);
-            for (entity, mut piece, mut transform) in piece_q.iter_mut() {
-                let normal_capture = piece.position == end.position;
-                let en_passant_capture = en_passant.map_or(false, |e| e.position == piece.position);
-                if normal_capture || en_passant_capture {
-                    commands.entity(entity).despawn();
+    let (start, end) = match (selected.start.clone(), selected.end.clone()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return,
+    };
+    // A move chosen while pieces slide is a premove, played once they land
+    if !slide_q.is_empty() {
+        return;
+    }
+    let promotion = if game.is_promotion(start.chess_sq, end.chess_sq) {
+        let promotion = selected.promotion;
+        match promotion {
+            None => {
+                // Show the pawn on its destination while the piece is being picked
+                for (piece, mut transform) in piece_q.iter_mut() {
+                    if piece.square == start.chess_sq {
+                        transform.translation = Vec3::new(end.position.x, end.position.y, 900.);
+                    }
                 }
-                if piece.position == start.position {
-                    *transform = Transform {
-                        translation: Vec3::new(end.position.x, end.position.y, 900.),
-                        ..default()
-                    };
-                    *piece = PieceComponent {
-                        position: end.position,
-                    };
+                selected.promotion = Some(Promotion::Picking);
+                return;
+            }
+            Some(Promotion::Picking) => return,
+            Some(Promotion::Chosen(piece)) => Some(piece),
+            Some(Promotion::Cancelled) => {
+                for (piece, mut transform) in piece_q.iter_mut() {
+                    if piece.square == start.chess_sq {
+                        transform.translation = Vec3::new(start.position.x, start.position.y, 900.);
+                    }
                 }
+                selected.reset();
+                return;
             }
         }
+    } else {
+        None
+    };
 
-        // Reset selecting after handled
-        selected.reset();
+    // `sync_pieces` moves the sprites after the board
+    let m = chess::ChessMove::new(start.chess_sq, end.chess_sq, promotion);
+    let before = game.clone();
+    let result = game.try_move(m);
+    if result.is_ok() {
+        takebacks.push(before);
+    }
+    move_evw.send(MoveAttempt { m, result });
+
+    // Reset selecting after handled
+    selected.reset();
+}
+
+fn promotion_picker(
+    mut commands: Commands,
+    pieces: Res<ChessPieceSprites>,
+    game: Res<Game>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    selected_q: Query<&SelectingSquares>,
+    choice_q: Query<Entity, With<PromotionChoice>>,
+) {
+    let selected = selected_q.single();
+    let picking = selected.promotion == Some(Promotion::Picking);
+    // The choices are drawn again on the new squares after a resize or a flip
+    let relayout = picking && (layout.is_changed() || orientation.is_changed());
+    if picking != choice_q.is_empty() && !relayout {
+        return;
+    }
+    for entity in choice_q.iter() {
+        commands.entity(entity).despawn();
+    }
+    if !picking {
+        return;
+    }
+
+    let end = match selected.end.as_ref() {
+        Some(end) => end,
+        None => return,
+    };
+    let color = game.board().side_to_move();
+    let piece_size = layout.piece_size();
+    for (sq, piece) in promotion_choices(end.chess_sq, color) {
+        let Vec2 { x, y } = square_position(sq, *orientation, piece_size);
+
+        commands
+            .spawn()
+            .insert(Name::new(format!("Promotion {}", piece)))
+            .insert(PromotionChoice)
+            .insert_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: PROMOTION_COLOR,
+                    ..default()
+                },
+                transform: Transform {
+                    translation: Vec3::new(x, y, 950.),
+                    scale: Vec3::new(piece_size, piece_size, 1.0),
+                    ..default()
+                },
+                ..default()
+            });
+
+        let mut sprite = TextureAtlasSprite::new(PieceSprite::from_chess(piece, color) as usize);
+        sprite.custom_size = Some(Vec2::splat(piece_size - 10.));
+        commands
+            .spawn()
+            .insert(PromotionChoice)
+            .insert_bundle(SpriteSheetBundle {
+                sprite,
+                texture_atlas: pieces.0.clone(),
+                transform: Transform {
+                    translation: Vec3::new(x, y, 951.),
+                    scale: Vec3::new(0.9, 0.9, 1.),
+                    ..default()
+                },
+                ..default()
+            });
+    }
+}
+
+/// Flips the board; `layout_board` re-positions everything.
+fn flip_board(keys: Res<Input<KeyCode>>, mut orientation: ResMut<BoardOrientation>) {
+    if keys.just_pressed(KeyCode::F) {
+        *orientation = orientation.flipped();
+    }
+}
+
+fn status_display(
+    mut status_evr: EventReader<StatusMessage>,
+    mut text_q: Query<&mut Text, With<StatusText>>,
+) {
+    if let Some(StatusMessage(message)) = status_evr.iter().last() {
+        for mut text in text_q.iter_mut() {
+            text.sections[0].value = message.clone();
+        }
+    }
+}
+
+/// Sends the position parsed from `fen`, or reports why it could not be loaded.
+fn load_fen(
+    fen: Result<Position, FenError>,
+    load_evw: &mut EventWriter<LoadPosition>,
+    status_evw: &mut EventWriter<StatusMessage>,
+) {
+    match fen {
+        Ok(position) => {
+            load_evw.send(LoadPosition(position));
+            status_evw.send(StatusMessage("Position loaded".to_string()));
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            status_evw.send(StatusMessage(format!("Invalid FEN: {}", err.reason)));
+        }
+    }
+}
+
+/// Ctrl, or either Cmd key on macOS.
+fn ctrl_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.pressed(KeyCode::LControl)
+        || keys.pressed(KeyCode::RControl)
+        || keys.pressed(KeyCode::LWin)
+        || keys.pressed(KeyCode::RWin)
+}
+
+/// Ctrl+V loads a FEN from the clipboard.
+fn paste_fen(
+    keys: Res<Input<KeyCode>>,
+    mut load_evw: EventWriter<LoadPosition>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    if !ctrl_pressed(&keys) || !keys.just_pressed(KeyCode::V) {
+        return;
+    }
+    let text = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text());
+    match text {
+        Ok(text) => load_fen(parse_fen(&text), &mut load_evw, &mut status_evw),
+        Err(err) => status_evw.send(StatusMessage(format!("Clipboard: {}", err))),
+    }
+}
+
+/// Ctrl+C or the "Copy FEN" button puts the FEN of the current position on the clipboard, or
+/// writes it to the `--fen-out` destination.
+fn copy_fen(
+    keys: Res<Input<KeyCode>>,
+    interaction_q: Query<&Interaction, (Changed<Interaction>, With<CopyFenButton>)>,
+    output: Res<FenOutput>,
+    game: Res<Game>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    let clicked = interaction_q
+        .iter()
+        .any(|interaction| *interaction == Interaction::Clicked);
+    if !clicked && !(ctrl_pressed(&keys) && keys.just_pressed(KeyCode::C)) {
+        return;
+    }
+
+    let fen = game.position().to_string();
+    let status = match &*output {
+        FenOutput::Clipboard => {
+            match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(fen)) {
+                Ok(()) => "FEN copied".to_string(),
+                Err(err) => format!("Clipboard: {}", err),
+            }
+        }
+        FenOutput::Stdout => {
+            println!("{}", fen);
+            "FEN written to stdout".to_string()
+        }
+        FenOutput::File(path) => match std::fs::write(path, fen + "\n") {
+            Ok(()) => format!("FEN written to {}", path.display()),
+            Err(err) => format!("Cannot write {}: {}", path.display(), err),
+        },
+    };
+    status_evw.send(StatusMessage(status));
+}
+
+/// Dropping a file on the window replays it if it is a PGN file, or loads the FEN in it.
+fn drop_file(
+    mut drop_evr: EventReader<FileDragAndDrop>,
+    mut load_evw: EventWriter<LoadPosition>,
+    mut pgn_evw: EventWriter<OpenPgn>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    for ev in drop_evr.iter() {
+        if let FileDragAndDrop::DroppedFile { path_buf, .. } = ev {
+            let is_pgn = path_buf
+                .extension()
+                .map_or(false, |ext| ext.eq_ignore_ascii_case("pgn"));
+            if is_pgn {
+                pgn_evw.send(OpenPgn(path_buf.clone()));
+            } else {
+                load_fen(read_fen_file(path_buf), &mut load_evw, &mut status_evw);
+            }
+        }
+    }
+}
+
+fn load_position(
+    mut load_evr: EventReader<LoadPosition>,
+    mut game: ResMut<Game>,
+    mut takebacks: ResMut<Takebacks>,
+    mut replay: ResMut<Replay>,
+    mut selected_q: Query<&mut SelectingSquares>,
+    start_q: Query<(), With<StartButton>>,
+) {
+    let position = match load_evr.iter().last() {
+        Some(LoadPosition(position)) => *position,
+        None => return,
+    };
+    *takebacks = Takebacks::default();
+    if replay.is_active() {
+        *replay = Replay::default();
+    }
+
+    let mut selected = selected_q.single_mut();
+    selected.reset();
+    selected.dragging = None;
+
+    // Once started, the clocks run on in the new position
+    *game = Game::new(position, game.time_control().clone());
+    if start_q.is_empty() {
+        game.start();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{translate_square_to_xy, translate_xy_to_center_coord};
-    use chess::Square;
+    use crate::harness::TestApp;
+    use crate::{format_duration, translate_square_to_xy, translate_xy_to_center_coord};
+    use bevy::utils::Duration;
+    use chess::{Piece, Square};
     use std::iter::zip;
 
+    const CASTLING_FEN: &str = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+
+    /// Clicks the king and its destination, then checks where the sprites ended up.
+    fn assert_castled(fen: &str, king: (Square, Square), rook: (Square, Square)) {
+        let mut app = TestApp::new(fen);
+        app.click(king.0);
+        app.click(king.1);
+        let board = *app.game().board();
+        assert_eq!(board.piece_on(king.1), Some(Piece::King));
+        assert_eq!(board.piece_on(rook.1), Some(Piece::Rook));
+        assert_eq!(board.piece_on(king.0), None);
+        assert_eq!(board.piece_on(rook.0), None);
+        // Every sprite shows the piece and colour on its square
+        app.assert_pieces_match_board();
+    }
+
+    #[test]
+    fn white_castles_kingside() {
+        assert_castled(
+            CASTLING_FEN,
+            (Square::E1, Square::G1),
+            (Square::H1, Square::F1),
+        );
+    }
+
+    #[test]
+    fn white_castles_queenside() {
+        assert_castled(
+            CASTLING_FEN,
+            (Square::E1, Square::C1),
+            (Square::A1, Square::D1),
+        );
+    }
+
+    #[test]
+    fn black_castles_kingside() {
+        let fen = CASTLING_FEN.replace(" w ", " b ");
+        assert_castled(&fen, (Square::E8, Square::G8), (Square::H8, Square::F8));
+    }
+
+    #[test]
+    fn black_castles_queenside() {
+        let fen = CASTLING_FEN.replace(" w ", " b ");
+        assert_castled(&fen, (Square::E8, Square::C8), (Square::A8, Square::D8));
+    }
+
     #[test]
     fn translate_coord_works() {
         let result = translate_xy_to_center_coord(3, 4);
@@ -614,4 +1298,11 @@ mod tests {
             assert_eq!(result, expect);
         }
     }
+
+    #[test]
+    fn formats_clock_times() {
+        assert_eq!(format_duration(&Duration::from_secs(65)), "01:05");
+        assert_eq!(format_duration(&Duration::from_secs(59 * 60 + 59)), "59:59");
+        assert_eq!(format_duration(&Duration::from_secs(90 * 60)), "1:30:00");
+    }
 }
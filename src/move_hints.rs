@@ -0,0 +1,136 @@
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+
+use crate::{game::Game, theme::Themes, SelectingSquares, SquareComponent};
+
+const RING_SEGMENTS: u32 = 32;
+
+/// Draws the legal destinations of the selected piece: dots on empty squares and rings on
+/// captures.
+pub struct MoveHintsPlugin;
+
+impl Plugin for MoveHintsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system_to_stage(StartupStage::PreStartup, load_hint_meshes)
+            .add_system(highlight_legal_moves)
+            .add_system(recolor_hints);
+    }
+}
+
+struct MoveHintMeshes {
+    dot: Mesh2dHandle,
+    ring: Mesh2dHandle,
+    material: Handle<ColorMaterial>,
+}
+
+/// The marker on a legal destination of the selected piece.
+#[derive(Component)]
+pub struct MoveHint;
+
+fn load_hint_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    themes: Res<Themes>,
+) {
+    // Unit sized, scaled by the square size like the squares themselves
+    commands.insert_resource(MoveHintMeshes {
+        dot: meshes.add(ring_mesh(0., 0.16)).into(),
+        ring: meshes.add(ring_mesh(0.42, 0.5)).into(),
+        material: materials.add(ColorMaterial::from(themes.current().move_hint)),
+    });
+}
+
+/// A flat ring between the `inner` and `outer` radius, a disc when `inner` is zero.
+fn ring_mesh(inner: f32, outer: f32) -> Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    for i in 0..RING_SEGMENTS {
+        let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+        let (sin, cos) = angle.sin_cos();
+        for radius in [inner, outer] {
+            positions.push([cos * radius, sin * radius, 0.]);
+            normals.push([0., 0., 1.]);
+            uvs.push([0.5 + cos * radius, 0.5 + sin * radius]);
+        }
+    }
+
+    let mut indices = Vec::new();
+    for i in 0..RING_SEGMENTS {
+        let next = (i + 1) % RING_SEGMENTS;
+        let (inner, outer) = (2 * i, 2 * i + 1);
+        let (next_inner, next_outer) = (2 * next, 2 * next + 1);
+        indices.extend_from_slice(&[inner, outer, next_outer, inner, next_outer, next_inner]);
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+fn recolor_hints(
+    themes: Res<Themes>,
+    meshes: Res<MoveHintMeshes>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if !themes.is_changed() {
+        return;
+    }
+    if let Some(material) = materials.get_mut(&meshes.material) {
+        material.color = themes.current().move_hint;
+    }
+}
+
+fn highlight_legal_moves(
+    mut commands: Commands,
+    meshes: Res<MoveHintMeshes>,
+    game: Res<Game>,
+    selected_q: Query<&SelectingSquares, Changed<SelectingSquares>>,
+    square_q: Query<&SquareComponent>,
+    hint_q: Query<Entity, With<MoveHint>>,
+) {
+    for selected in selected_q.iter() {
+        for entity in hint_q.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        let start = match (&selected.start, selected.promotion) {
+            (Some(start), None) => start,
+            _ => continue,
+        };
+        let board = game.board();
+        let is_pawn = board.piece_on(start.chess_sq) == Some(chess::Piece::Pawn);
+        for dest in game.legal_destinations(start.chess_sq) {
+            let square = match square_q.iter().find(|&sq| sq.chess_sq == dest) {
+                Some(square) => square,
+                None => continue,
+            };
+            // A pawn changing file always captures, en passant included
+            let capture = board.piece_on(dest).is_some()
+                || (is_pawn && dest.get_file() != start.chess_sq.get_file());
+            let mesh = if capture { &meshes.ring } else { &meshes.dot };
+
+            commands
+                .spawn()
+                .insert(Name::new(format!("MoveHint {}", dest)))
+                .insert(MoveHint)
+                .insert_bundle(MaterialMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: meshes.material.clone(),
+                    transform: Transform {
+                        translation: Vec3::new(square.position.x, square.position.y, 3.),
+                        scale: Vec3::new(square.piece_size, square.piece_size, 1.),
+                        ..default()
+                    },
+                    ..default()
+                });
+        }
+    }
+}
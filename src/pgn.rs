@@ -0,0 +1,464 @@
+use bevy::{prelude::*, utils::Duration};
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    ctrl_pressed,
+    fen::{parse_fen, Position},
+    game::{Game, GameEndReason, GameResult},
+    history::MoveHistory,
+    replay::Replay,
+    time_control::{Increment, TimeControl, TimeStage},
+    MoveAttempt, StatusMessage,
+};
+
+/// Export lines are kept below 80 characters.
+const LINE_LENGTH: usize = 79;
+
+/// Writes the game as PGN on Ctrl+S and when a move played or a flag falling ends it, not
+/// when a loaded position, a takeback or a replay shows a finished game.
+pub struct PgnExportPlugin;
+
+impl Plugin for PgnExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(save_pgn);
+    }
+}
+
+/// Where the PGN of the game is written.
+pub enum PgnOutput {
+    /// Added after the games already in the file, `game.pgn` unless `--pgn-out` is given.
+    Archive(PathBuf),
+    /// Replacing the file, from `--pgn-out <path>`.
+    File(PathBuf),
+}
+
+impl Default for PgnOutput {
+    fn default() -> Self {
+        Self::Archive(PathBuf::from("game.pgn"))
+    }
+}
+
+impl PgnOutput {
+    /// Writes `pgn`, returning whether it was added to games already in the file.
+    fn write(&self, pgn: &str) -> io::Result<bool> {
+        match self {
+            Self::Archive(path) => append_pgn(path, pgn),
+            Self::File(path) => std::fs::write(path, pgn).map(|()| false),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Self::Archive(path) | Self::File(path) => path,
+        }
+    }
+}
+
+/// Adds `pgn` at the end of the file at `path`, after a blank line if it has games already.
+fn append_pgn(path: &Path, pgn: &str) -> io::Result<bool> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let has_games = file.metadata()?.len() > 0;
+    if has_games {
+        writeln!(file)?;
+    }
+    file.write_all(pgn.as_bytes())?;
+    Ok(has_games)
+}
+
+/// A move of a PGN game, with the clock time from its `%clk` comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnMove {
+    pub san: String,
+    pub clock: Option<Duration>,
+}
+
+/// A game as PGN describes it: tag pairs, the main line and the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>,
+    pub result: String,
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        Self {
+            tags: Vec::new(),
+            moves: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+}
+
+impl PgnGame {
+    /// The record of the game so far, dated `date` (`YYYY.MM.DD`).
+    pub fn from_history(
+        history: &MoveHistory,
+        time_control: &TimeControl,
+        result: Option<GameResult>,
+        date: &str,
+    ) -> Self {
+        let result_tag = result.map_or("*", |result| result.tag());
+        let mut tags = vec![
+            ("Event", "Casual game".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", date.to_string()),
+            ("Round", "-".to_string()),
+            ("White", "?".to_string()),
+            ("Black", "?".to_string()),
+            ("Result", result_tag.to_string()),
+        ];
+        if history.start != Position::default() {
+            tags.push(("SetUp", "1".to_string()));
+            tags.push(("FEN", history.start.to_string()));
+        }
+        let white = time_control_tag(time_control.stages(chess::Color::White));
+        let black = time_control_tag(time_control.stages(chess::Color::Black));
+        if white == black {
+            tags.push(("TimeControl", white));
+        } else {
+            // PGN has a single time control for both sides
+            tags.push(("TimeControl", "?".to_string()));
+            tags.push(("WhiteTimeControl", white));
+            tags.push(("BlackTimeControl", black));
+        }
+        if let Some(result) = result {
+            let termination = match result.reason() {
+                GameEndReason::Timeout | GameEndReason::TimeoutVsInsufficientMaterial => {
+                    "time forfeit"
+                }
+                GameEndReason::Checkmate | GameEndReason::Stalemate => "normal",
+            };
+            tags.push(("Termination", termination.to_string()));
+        }
+
+        let mut black_to_move = history.start.board.side_to_move() == chess::Color::Black;
+        let moves = history
+            .moves
+            .iter()
+            .map(|record| {
+                let clock = if black_to_move {
+                    record.black_clock
+                } else {
+                    record.white_clock
+                };
+                black_to_move = !black_to_move;
+                PgnMove {
+                    san: record.san.clone(),
+                    clock: Some(clock),
+                }
+            })
+            .collect();
+
+        Self {
+            tags: tags
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+            moves,
+            result: result_tag.to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from, its `FEN` tag if it has one.
+    pub fn start_position(&self) -> Result<Position, String> {
+        match self.tag("FEN") {
+            Some(fen) => parse_fen(fen).map_err(|err| err.to_string()),
+            None => Ok(Position::default()),
+        }
+    }
+}
+
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(f)?;
+
+        let start = self.start_position().unwrap_or_default();
+        let mut number = start.counters.fullmove_number;
+        let mut black_to_move = start.board.side_to_move() == chess::Color::Black;
+        // Black's move number is repeated after a comment interrupts the pair
+        let mut number_black = true;
+        let mut tokens = Vec::new();
+        for m in &self.moves {
+            if !black_to_move {
+                tokens.push(format!("{}.", number));
+            } else if number_black {
+                tokens.push(format!("{}...", number));
+            }
+            tokens.push(m.san.clone());
+            if let Some(clock) = m.clock {
+                tokens.push(format!("{{[%clk {}]}}", format_clock(clock)));
+            }
+            number_black = m.clock.is_some();
+            if black_to_move {
+                number += 1;
+            }
+            black_to_move = !black_to_move;
+        }
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+fn format_clock(clock: Duration) -> String {
+    let seconds = clock.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+/// The PGN `TimeControl` value of a side's stages, e.g. `40/5400+30:1800+30`. PGN has no
+/// notation for delays, so they are left out.
+fn time_control_tag(stages: &[TimeStage]) -> String {
+    let stages: Vec<String> = stages
+        .iter()
+        .map(|stage| {
+            let moves = stage.moves.map_or(String::new(), |n| format!("{}/", n));
+            let increment = match stage.increment {
+                Increment::Fischer(increment) => format!("+{}", increment.as_secs()),
+                Increment::None | Increment::Bronstein(_) | Increment::Simple(_) => String::new(),
+            };
+            format!("{}{}{}", moves, stage.base.as_secs(), increment)
+        })
+        .collect();
+    stages.join(":")
+}
+
+/// Today's date as `YYYY.MM.DD` (UTC).
+fn pgn_date(now: SystemTime) -> String {
+    let days = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86_400) as i64;
+    // Civil date from days since 1970-01-01, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{}.{:02}.{:02}", year, month, day)
+}
+
+fn save_pgn(
+    keys: Res<Input<KeyCode>>,
+    output: Res<PgnOutput>,
+    game: Res<Game>,
+    replay: Res<Replay>,
+    mut move_evr: EventReader<MoveAttempt>,
+    mut was_running: Local<bool>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    let ended_by_move = move_evr.iter().any(|attempt| attempt.result.is_ok());
+    let flag_fell = *was_running && game.result().map_or(false, |r| r.reason().is_timeout());
+    *was_running = game.is_running();
+    let game_ended = (ended_by_move || flag_fell) && game.result().is_some() && !replay.is_active();
+    let requested = ctrl_pressed(&keys) && keys.just_pressed(KeyCode::S);
+    if !game_ended && !requested {
+        return;
+    }
+
+    let date = pgn_date(SystemTime::now());
+    let pgn = PgnGame::from_history(game.history(), game.time_control(), game.result(), &date);
+    let path = output.path().display();
+    let status = match output.write(&pgn.to_string()) {
+        Ok(true) => format!("Game added to the games in {}", path),
+        Ok(false) => format!("Game saved to {}", path),
+        Err(err) => format!("Cannot write {}: {}", path, err),
+    };
+    status_evw.send(StatusMessage(status));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pgn_date, PgnGame, PgnOutput};
+    use crate::fen::{parse_fen, Position};
+    use crate::game::{GameEndReason, GameResult};
+    use crate::history::{MoveHistory, MoveRecord};
+    use crate::san::{from_san, to_san};
+    use crate::time_control::TimeControl;
+    use bevy::utils::Duration;
+    use std::str::FromStr;
+    use std::time::SystemTime;
+
+    /// Plays `moves` from `start`, taking a second off the mover's clock each move.
+    fn history(start: Position, moves: &[&str]) -> MoveHistory {
+        let mut history = MoveHistory::new(start);
+        let mut board = start.board;
+        let mut clocks = [Duration::from_secs(300); 2];
+        for uci in moves {
+            let m = chess::ChessMove::from_str(uci).unwrap();
+            clocks[board.side_to_move().to_index()] -= Duration::from_secs(1);
+            history.moves.push(MoveRecord {
+                san: to_san(&board, m),
+                uci: uci.to_string(),
+                fen_before: board.to_string(),
+                white_clock: clocks[0],
+                black_clock: clocks[1],
+            });
+            board = board.make_move_new(m);
+        }
+        history
+    }
+
+    /// The example game of the PGN standard (section 2.3), as printed there.
+    const STANDARD_EXAMPLE: &str = include_str!("../tests/pgn-standard-example.pgn");
+
+    #[test]
+    fn writes_the_example_game_of_the_standard() {
+        let (tags, movetext) = STANDARD_EXAMPLE.split_once("\n\n").unwrap();
+        // Its one comment is not something an export writes
+        let movetext = movetext.replace("{This opening is called the Ruy Lopez.} ", "");
+        let expected: Vec<&str> = movetext.split_whitespace().collect();
+
+        let mut board = chess::Board::default();
+        let mut moves = Vec::new();
+        for san in expected
+            .iter()
+            .filter(|token| !token.ends_with('.') && **token != "1/2-1/2")
+        {
+            let m = from_san(&board, san).unwrap();
+            moves.push(m.to_string());
+            board = board.make_move_new(m);
+        }
+        let moves: Vec<&str> = moves.iter().map(String::as_str).collect();
+        let mut game = PgnGame::from_history(
+            &history(Position::default(), &moves),
+            &TimeControl::default(),
+            None,
+            "1992.11.04",
+        );
+        game.tags = [
+            ("Event", "F/S Return Match"),
+            ("Site", "Belgrade, Serbia JUG"),
+            ("Date", "1992.11.04"),
+            ("Round", "29"),
+            ("White", "Fischer, Robert J."),
+            ("Black", "Spassky, Boris V."),
+            ("Result", "1/2-1/2"),
+        ]
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        for m in &mut game.moves {
+            m.clock = None;
+        }
+        // A draw by agreement, which the game does not record
+        game.result = "1/2-1/2".to_string();
+
+        let pgn = game.to_string();
+        let (written_tags, written_movetext) = pgn.split_once("\n\n").unwrap();
+        assert_eq!(written_tags, tags);
+        // The standard's lines are wrapped by hand, a little past the export width
+        assert_eq!(
+            written_movetext.split_whitespace().collect::<Vec<_>>(),
+            expected
+        );
+        assert!(pgn.lines().all(|line| line.len() <= 79));
+    }
+
+    #[test]
+    fn writes_clocks_in_export_format() {
+        let moves = ["f2f3", "e7e5", "g2g4", "d8h4"];
+        let result = Some(GameResult::BlackWins(GameEndReason::Checkmate));
+        let game = PgnGame::from_history(
+            &history(Position::default(), &moves),
+            &TimeControl::default(),
+            result,
+            "2024.01.31",
+        );
+        let pgn = game.to_string();
+        let expected = "[Event \"Casual game\"]\n\
+                        [Site \"?\"]\n\
+                        [Date \"2024.01.31\"]\n\
+                        [Round \"-\"]\n\
+                        [White \"?\"]\n\
+                        [Black \"?\"]\n\
+                        [Result \"0-1\"]\n\
+                        [TimeControl \"600\"]\n\
+                        [Termination \"normal\"]\n\
+                        \n\
+                        1. f3 {[%clk 0:04:59]} 1... e5 {[%clk 0:04:59]} 2. g4 {[%clk 0:04:58]} 2...\n\
+                        Qh4# {[%clk 0:04:58]} 0-1\n";
+        assert_eq!(pgn, expected);
+    }
+
+    #[test]
+    fn archives_games_after_the_earlier_ones() {
+        let path = std::env::temp_dir().join(format!("archive-{}.pgn", std::process::id()));
+        let output = PgnOutput::Archive(path.clone());
+        assert!(!output.write("1. e4 *\n").unwrap());
+        assert!(output.write("1. d4 *\n").unwrap());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(text, "1. e4 *\n\n1. d4 *\n");
+    }
+
+    #[test]
+    fn records_a_custom_start_position() {
+        let start =
+            parse_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        let time_control = TimeControl::presets()
+            .into_iter()
+            .find(|preset| preset.name == "40/90+30")
+            .unwrap();
+        let game = PgnGame::from_history(
+            &history(start, &["e7e5", "g1f3"]),
+            &time_control,
+            None,
+            "2024.01.31",
+        );
+        assert_eq!(game.tag("SetUp"), Some("1"));
+        assert_eq!(game.tag("FEN"), Some(start.to_string().as_str()));
+        assert_eq!(game.tag("TimeControl"), Some("40/5400+30:1800+30"));
+        let pgn = game.to_string();
+        assert!(pgn.contains("\n1... e5 {[%clk 0:04:59]} 2. Nf3"));
+    }
+
+    #[test]
+    fn formats_dates() {
+        let day = |days: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(days * 86_400);
+        assert_eq!(pgn_date(day(0)), "1970.01.01");
+        assert_eq!(pgn_date(day(19_782)), "2024.02.29");
+    }
+}
@@ -0,0 +1,280 @@
+use bevy::utils::Duration;
+use std::fmt;
+
+use crate::pgn::{PgnGame, PgnMove};
+
+/// Why a PGN file could not be read, with the 1-based number of the game at fault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub game: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "game {}: {}", self.game, self.reason)
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+/// Reads every game of a PGN file. Comments other than `%clk`, NAGs and variations are
+/// skipped; the SAN moves are not checked against the board.
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut in_game = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let game_number = games.len() + 1;
+        let error = move |reason: String| PgnError {
+            game: game_number,
+            reason,
+        };
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ';' || (c == '%' && (i == 0 || chars[i - 1] == '\n')) {
+            // Rest-of-line comment or escape line
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '[' {
+            if !game.moves.is_empty() {
+                return Err(error(
+                    "moves without a result before the next tags".to_string(),
+                ));
+            }
+            let (tag, end) = parse_tag(&chars, i).map_err(error)?;
+            game.tags.push(tag);
+            in_game = true;
+            i = end;
+        } else if c == '{' {
+            let end =
+                find(&chars, i, '}').ok_or_else(|| error("unterminated comment".to_string()))?;
+            let comment: String = chars[i + 1..end].iter().collect();
+            if let (Some(clock), Some(last)) = (parse_clock(&comment), game.moves.last_mut()) {
+                last.clock = Some(clock);
+            }
+            i = end + 1;
+        } else if c == '(' {
+            i = skip_variation(&chars, i)
+                .ok_or_else(|| error("unterminated variation".to_string()))?;
+        } else if c == ')' || c == ']' || c == '}' {
+            return Err(error(format!("unexpected '{}'", c)));
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() && !"{}()[];".contains(chars[i]) {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            in_game = true;
+            if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) {
+                game.result = token;
+                games.push(std::mem::take(&mut game));
+                in_game = false;
+                continue;
+            }
+            // Move numbers may be attached to the move, as in `1.e4`, but `0-0` is castling
+            let number = token.len() - token.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let san = if number > 0 && token[number..].starts_with('.') {
+                token[number..].trim_start_matches('.')
+            } else {
+                token.as_str()
+            };
+            let san = san.trim_end_matches(|c: char| c == '!' || c == '?');
+            if !san.is_empty() && !san.starts_with('$') {
+                game.moves.push(PgnMove {
+                    san: san.to_string(),
+                    clock: None,
+                });
+            }
+        }
+    }
+    // Be lenient with a missing result at the end of the file
+    if in_game {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+/// Parses the tag pair starting at `chars[start] == '['`, returning it and the index after it.
+fn parse_tag(chars: &[char], start: usize) -> Result<((String, String), usize), String> {
+    let mut i = start + 1;
+    let skip_whitespace = |i: &mut usize| {
+        while *i < chars.len() && chars[*i].is_whitespace() {
+            *i += 1;
+        }
+    };
+    skip_whitespace(&mut i);
+    let name_start = i;
+    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+        i += 1;
+    }
+    let name: String = chars[name_start..i].iter().collect();
+    if name.is_empty() {
+        return Err("tag without a name".to_string());
+    }
+    skip_whitespace(&mut i);
+    if chars.get(i) != Some(&'"') {
+        return Err(format!("tag {} has no value", name));
+    }
+    i += 1;
+    let mut value = String::new();
+    loop {
+        match chars.get(i) {
+            Some('\\') => {
+                if let Some(&escaped) = chars.get(i + 1) {
+                    value.push(escaped);
+                }
+                i += 2;
+            }
+            Some('"') => break,
+            Some(&c) => {
+                value.push(c);
+                i += 1;
+            }
+            None => return Err(format!("tag {} is not terminated", name)),
+        }
+    }
+    i += 1;
+    skip_whitespace(&mut i);
+    if chars.get(i) != Some(&']') {
+        return Err(format!("tag {} is not closed", name));
+    }
+    Ok(((name, value), i + 1))
+}
+
+fn find(chars: &[char], start: usize, target: char) -> Option<usize> {
+    (start..chars.len()).find(|&i| chars[i] == target)
+}
+
+/// The index after the variation starting at `chars[start] == '('`, nested ones included.
+fn skip_variation(chars: &[char], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            '{' => i = find(chars, i, '}')?,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The clock time of a `[%clk H:MM:SS]` command in a comment, seconds may have a fraction.
+fn parse_clock(comment: &str) -> Option<Duration> {
+    let start = comment.find("[%clk")? + "[%clk".len();
+    let time = comment[start..]
+        .trim_start()
+        .split(|c: char| c == ']' || c == ' ')
+        .next()?;
+    let (whole, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut seconds: u64 = 0;
+    for part in whole.split(':') {
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        seconds = seconds.checked_mul(60)?.checked_add(part.parse().ok()?)?;
+    }
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // Nanoseconds, from the first nine digits
+    let digits = &fraction[..fraction.len().min(9)];
+    let nanos = if digits.is_empty() {
+        0
+    } else {
+        format!("{:0<9}", digits).parse().ok()?
+    };
+    Some(Duration::new(seconds, nanos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_clock, parse_pgn};
+    use crate::pgn::{PgnGame, PgnMove};
+    use bevy::utils::Duration;
+
+    #[test]
+    fn reads_back_exported_games() {
+        let game = PgnGame {
+            tags: vec![
+                ("Event".to_string(), "Casual game".to_string()),
+                ("SetUp".to_string(), "1".to_string()),
+                (
+                    "FEN".to_string(),
+                    "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_string(),
+                ),
+                ("Result".to_string(), "*".to_string()),
+            ],
+            moves: ["e5", "Nf3", "Nc6"]
+                .iter()
+                .map(|san| PgnMove {
+                    san: san.to_string(),
+                    clock: Some(Duration::from_secs(299)),
+                })
+                .collect(),
+            result: "*".to_string(),
+        };
+        assert_eq!(parse_pgn(&game.to_string()).unwrap(), vec![game]);
+    }
+
+    #[test]
+    fn reads_multiple_annotated_games() {
+        let pgn = "[Event \"A \\\"quoted\\\" name\"]\n\
+                   [Result \"1-0\"]\n\
+                   \n\
+                   1.e4 $1 e5!? (1... c5 {Sicilian} 2. Nf3) 2. Nf3 ; rest of line\n\
+                   {[%clk 0:01:02.5]} 1-0\n\
+                   \n\
+                   [Event \"Second\"]\n\
+                   \n\
+                   1. d4 *\n";
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("A \"quoted\" name"));
+        let sans: Vec<&str> = games[0].moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(sans, ["e4", "e5", "Nf3"]);
+        assert_eq!(games[0].moves[2].clock, Some(Duration::from_millis(62_500)));
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves.len(), 1);
+        assert_eq!(games[1].result, "*");
+
+        let error = parse_pgn("1. e4 *\n[Event \"x\"\n").unwrap_err();
+        assert_eq!(error.game, 2);
+    }
+
+    #[test]
+    fn reads_castling_written_with_zeros() {
+        let games = parse_pgn("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4.0-0 0-0-0 *\n").unwrap();
+        let sans: Vec<&str> = games[0].moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(sans[6..], ["0-0", "0-0-0"]);
+    }
+
+    #[test]
+    fn rejects_clocks_that_are_not_times() {
+        assert_eq!(
+            parse_clock("[%clk 1:30:00]"),
+            Some(Duration::from_secs(5400))
+        );
+        assert_eq!(
+            parse_clock("[%clk 0:00:01.25]"),
+            Some(Duration::from_millis(1250))
+        );
+        assert_eq!(parse_clock("[%clk -0:00:05]"), None);
+        assert_eq!(parse_clock("[%clk nan]"), None);
+        assert_eq!(parse_clock("[%clk 1e300]"), None);
+        assert_eq!(parse_clock("[%clk 99999999999999999999:00]"), None);
+        assert_eq!(parse_clock("[%clk 0:00:01.5x]"), None);
+    }
+}
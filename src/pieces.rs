@@ -0,0 +1,297 @@
+use bevy::{prelude::*, utils::Duration};
+
+use crate::{
+    arg_value, game::Game, layout::BoardLayout, square_position, BoardOrientation,
+    ChessPieceSprites, PieceComponent, PieceSprite, StatusMessage, DRAG_Z,
+};
+
+/// The durations A cycles through, from instant to slow.
+const SPEEDS: [(&str, u64); 4] = [
+    ("instant", 0),
+    ("fast", 100),
+    ("normal", 250),
+    ("slow", 500),
+];
+
+/// Keeps the piece sprites showing the game's board. Whatever changed the board (a move, a
+/// takeback, a replay or a loaded position), sprites slide to their new squares, captured
+/// ones fade out and new ones appear. Castling slides the king and the rook together.
+/// `--animation-ms <ms>` sets how long that takes and A cycles through the presets.
+pub struct PiecesPlugin;
+
+impl Plugin for PiecesPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AnimationSettings::from_args())
+            .add_system(cycle_animation_speed)
+            .add_system(slide_pieces)
+            .add_system(fade_pieces)
+            .add_system(finish_animations.after(slide_pieces).after(fade_pieces))
+            // After every system that may have changed the game this frame
+            .add_system_to_stage(CoreStage::PostUpdate, sync_pieces);
+    }
+}
+
+pub struct AnimationSettings {
+    /// How long a piece takes to move; zero moves it instantly.
+    pub duration: Duration,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_millis(SPEEDS[2].1),
+        }
+    }
+}
+
+impl AnimationSettings {
+    fn from_args() -> Self {
+        match arg_value("--animation-ms").and_then(|millis| millis.parse().ok()) {
+            Some(millis) => Self {
+                duration: Duration::from_millis(millis),
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// How far into the animation `elapsed` seconds are, from 0 to 1.
+    fn progress(&self, elapsed: f32) -> f32 {
+        let duration = self.duration.as_secs_f32();
+        if duration > 0. {
+            (elapsed / duration).min(1.)
+        } else {
+            1.
+        }
+    }
+}
+
+/// Moves a piece sprite from `from` to the square of its `PieceComponent`.
+#[derive(Component)]
+pub struct Slide {
+    from: Vec2,
+    elapsed: f32,
+}
+
+/// A captured piece sprite on its way out, no longer a `PieceComponent`.
+#[derive(Component)]
+pub struct FadeOut {
+    elapsed: f32,
+}
+
+/// Starts slowly, speeds up and slows down again for `t` from 0 to 1.
+fn ease_in_out(t: f32) -> f32 {
+    if t < 0.5 {
+        4. * t * t * t
+    } else {
+        1. - (2. - 2. * t).powi(3) / 2.
+    }
+}
+
+fn spawn_piece_sprite(
+    commands: &mut Commands,
+    pieces: &ChessPieceSprites,
+    piece: PieceComponent,
+    vs: Vec2,
+    piece_size: f32,
+) {
+    let mut sprite =
+        TextureAtlasSprite::new(PieceSprite::from_chess(piece.piece, piece.color) as usize);
+    sprite.custom_size = Some(Vec2::splat(piece_size - 10.));
+    commands
+        .spawn()
+        .insert(Name::new(piece.piece.to_string(piece.color)))
+        .insert(piece)
+        .insert_bundle(SpriteSheetBundle {
+            sprite,
+            texture_atlas: pieces.0.clone(),
+            transform: Transform {
+                translation: Vec3::new(vs.x, vs.y, 900.),
+                scale: Vec3::new(0.9, 0.9, 1.),
+                ..default()
+            },
+            ..default()
+        });
+}
+
+fn sync_pieces(
+    mut commands: Commands,
+    game: Res<Game>,
+    pieces: Res<ChessPieceSprites>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    settings: Res<AnimationSettings>,
+    mut piece_q: Query<(Entity, &PieceComponent, &mut Transform)>,
+) {
+    if !game.is_changed() {
+        return;
+    }
+    let board = game.board();
+    let shows = |piece: &PieceComponent| {
+        board.piece_on(piece.square) == Some(piece.piece)
+            && board.color_on(piece.square) == Some(piece.color)
+    };
+    let instant = settings.duration.is_zero();
+
+    // Sprites no longer showing the piece on their square, and where they are drawn
+    let mut stale: Vec<(Entity, PieceComponent, Vec2)> = piece_q
+        .iter()
+        .filter(|(_, piece, _)| !shows(piece))
+        .map(|(entity, piece, transform)| (entity, *piece, transform.translation.truncate()))
+        .collect();
+
+    for &square in chess::ALL_SQUARES.iter() {
+        let wanted = match board.piece_on(square).zip(board.color_on(square)) {
+            Some((piece, color)) => PieceComponent {
+                square,
+                piece,
+                color,
+            },
+            None => continue,
+        };
+        if piece_q.iter().any(|(_, piece, _)| *piece == wanted) {
+            continue;
+        }
+        // The nearest sprite of the same piece slides over, otherwise a new one appears
+        let to = square_position(square, *orientation, layout.piece_size());
+        let nearest = stale
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, piece, _))| piece.piece == wanted.piece && piece.color == wanted.color)
+            .min_by(|(_, (_, _, a)), (_, (_, _, b))| a.distance(to).total_cmp(&b.distance(to)))
+            .map(|(i, _)| i);
+        match nearest {
+            Some(i) => {
+                let (entity, _, from) = stale.remove(i);
+                commands.entity(entity).insert(wanted);
+                if instant {
+                    if let Ok((_, _, mut transform)) = piece_q.get_mut(entity) {
+                        transform.translation = to.extend(900.);
+                    }
+                } else {
+                    commands.entity(entity).insert(Slide { from, elapsed: 0. });
+                }
+            }
+            None => spawn_piece_sprite(&mut commands, &pieces, wanted, to, layout.piece_size()),
+        }
+    }
+
+    for (entity, _, _) in stale {
+        if instant {
+            commands.entity(entity).despawn();
+        } else {
+            commands
+                .entity(entity)
+                .remove::<PieceComponent>()
+                .remove::<Slide>()
+                .insert(FadeOut { elapsed: 0. });
+        }
+    }
+}
+
+fn slide_pieces(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<AnimationSettings>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    mut piece_q: Query<(Entity, &PieceComponent, &mut Transform, &mut Slide)>,
+) {
+    for (entity, piece, mut transform, mut slide) in piece_q.iter_mut() {
+        slide.elapsed += time.delta_seconds();
+        let t = settings.progress(slide.elapsed);
+        // Above the other pieces until it lands
+        let z = if t < 1. { DRAG_Z } else { 900. };
+        let to = square_position(piece.square, *orientation, layout.piece_size());
+        transform.translation = slide.from.lerp(to, ease_in_out(t)).extend(z);
+        if t >= 1. {
+            commands.entity(entity).remove::<Slide>();
+        }
+    }
+}
+
+fn fade_pieces(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<AnimationSettings>,
+    mut fade_q: Query<(Entity, &mut TextureAtlasSprite, &mut FadeOut)>,
+) {
+    for (entity, mut sprite, mut fade) in fade_q.iter_mut() {
+        fade.elapsed += time.delta_seconds();
+        let t = settings.progress(fade.elapsed);
+        if t >= 1. {
+            commands.entity(entity).despawn();
+        } else {
+            sprite.color.set_a(1. - ease_in_out(t));
+        }
+    }
+}
+
+/// Ends the animations when the board is resized or flipped, as they run between positions
+/// of the old layout.
+fn finish_animations(
+    mut commands: Commands,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    mut slide_q: Query<(Entity, &PieceComponent, &mut Transform), With<Slide>>,
+    fade_q: Query<Entity, With<FadeOut>>,
+) {
+    if !layout.is_changed() && !orientation.is_changed() {
+        return;
+    }
+    for (entity, piece, mut transform) in slide_q.iter_mut() {
+        let position = square_position(piece.square, *orientation, layout.piece_size());
+        transform.translation = position.extend(900.);
+        commands.entity(entity).remove::<Slide>();
+    }
+    for entity in fade_q.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn cycle_animation_speed(
+    keys: Res<Input<KeyCode>>,
+    mut settings: ResMut<AnimationSettings>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    if !keys.just_pressed(KeyCode::A) {
+        return;
+    }
+    // The next slower preset, wrapping around to instant
+    let current = settings.duration;
+    let (name, millis) = SPEEDS
+        .iter()
+        .copied()
+        .find(|&(_, millis)| Duration::from_millis(millis) > current)
+        .unwrap_or(SPEEDS[0]);
+    settings.duration = Duration::from_millis(millis);
+    status_evw.send(StatusMessage(format!("Animation: {}", name)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ease_in_out, AnimationSettings};
+    use bevy::utils::Duration;
+
+    #[test]
+    fn eases_in_and_out() {
+        assert_eq!(ease_in_out(0.), 0.);
+        assert_eq!(ease_in_out(0.5), 0.5);
+        assert_eq!(ease_in_out(1.), 1.);
+        assert!(ease_in_out(0.1) < 0.1);
+        assert!(ease_in_out(0.9) > 0.9);
+    }
+
+    #[test]
+    fn finishes_at_once_when_instant() {
+        let normal = AnimationSettings {
+            duration: Duration::from_millis(200),
+        };
+        assert_eq!(normal.progress(0.1), 0.5);
+        assert_eq!(normal.progress(0.3), 1.);
+
+        let instant = AnimationSettings {
+            duration: Duration::ZERO,
+        };
+        assert_eq!(instant.progress(0.), 1.);
+    }
+}
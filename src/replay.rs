@@ -0,0 +1,320 @@
+use bevy::{prelude::*, utils::Duration};
+use std::path::PathBuf;
+
+use crate::{
+    arg_value,
+    fen::Position,
+    game::Game,
+    history::MoveRecord,
+    layout::{BoardLayout, PanelItem, PanelSlot, ScaledText},
+    pgn::PgnGame,
+    pgn_import::parse_pgn,
+    san::{from_san, to_san},
+    takeback::Takebacks,
+    theme::ThemeFonts,
+    BoardOrientation, SelectingSquares, StatusMessage, FONT_COLOR,
+};
+
+const REPLAY_FONT_SIZE: f32 = 10.0;
+
+/// Replays the games of PGN files dropped on the window or given with `--pgn <path>`.
+/// Page Up/Down picks the game, Left/Right steps through it, Home/End jumps to its start or
+/// end and Escape leaves the replay, back to the game being played.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Replay>()
+            .add_event::<OpenPgn>()
+            .add_startup_system(spawn_replay_text)
+            .add_startup_system(open_pgn_from_args)
+            .add_system(open_pgn)
+            .add_system(replay_keys.after(open_pgn))
+            .add_system(show_replay.after(replay_keys));
+    }
+}
+
+/// Opens a PGN file for replay.
+pub struct OpenPgn(pub PathBuf);
+
+/// The games of the open PGN file and the position shown.
+#[derive(Default)]
+pub struct Replay {
+    games: Vec<PgnGame>,
+    game: usize,
+    /// The start position of the game and the position after each of its readable moves.
+    positions: Vec<Position>,
+    records: Vec<MoveRecord>,
+    ply: usize,
+    /// The game being played and its takebacks, set aside while replaying.
+    live: Option<(Game, Takebacks)>,
+}
+
+impl Replay {
+    pub fn is_active(&self) -> bool {
+        !self.games.is_empty()
+    }
+
+    /// Reads the moves of game `index`, stopping at the first that cannot be played, which is
+    /// returned as an error.
+    fn select_game(&mut self, index: usize) -> Result<(), String> {
+        self.game = index;
+        self.ply = 0;
+        self.records.clear();
+        self.positions.clear();
+
+        let game = &self.games[index];
+        let mut position = match game.start_position() {
+            Ok(position) => position,
+            Err(reason) => {
+                // Something is shown all the same
+                self.positions.push(Position::default());
+                return Err(format!("game {}: {}", index + 1, reason));
+            }
+        };
+        self.positions.push(position);
+
+        // Time left on each clock, White's first
+        let mut clocks = [Duration::ZERO; 2];
+        for (ply, pgn_move) in game.moves.iter().enumerate() {
+            let m = from_san(&position.board, &pgn_move.san).ok_or_else(|| {
+                format!(
+                    "game {}, ply {}: cannot play '{}'",
+                    index + 1,
+                    ply + 1,
+                    pgn_move.san
+                )
+            })?;
+            if let Some(clock) = pgn_move.clock {
+                clocks[position.board.side_to_move().to_index()] = clock;
+            }
+            self.records.push(MoveRecord {
+                san: to_san(&position.board, m),
+                uci: m.to_string(),
+                fen_before: position.to_string(),
+                white_clock: clocks[0],
+                black_clock: clocks[1],
+            });
+            position = Position {
+                board: position.board.make_move_new(m),
+                counters: position.counters.after_move(&position.board, m),
+            };
+            self.positions.push(position);
+        }
+        Ok(())
+    }
+
+    fn headers(&self) -> String {
+        let game = &self.games[self.game];
+        let tag = |name: &str| game.tag(name).unwrap_or("?");
+        format!(
+            "Game {}/{}, ply {}/{}\n{} - {}\n{}\n{}",
+            self.game + 1,
+            self.games.len(),
+            self.ply,
+            self.records.len(),
+            tag("White"),
+            tag("Black"),
+            tag("Event"),
+            game.result
+        )
+    }
+}
+
+#[derive(Component)]
+struct ReplayText;
+
+fn spawn_replay_text(
+    mut commands: Commands,
+    fonts: Res<ThemeFonts>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+) {
+    let item = PanelItem::stacked(PanelSlot::Replay);
+    let (position, size) = layout.place(&item, *orientation);
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: fonts.font.clone(),
+                    font_size: REPLAY_FONT_SIZE * layout.scale,
+                    color: FONT_COLOR,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position,
+                size,
+                ..default()
+            }),
+        )
+        .insert(Name::new("ReplayText"))
+        .insert(item)
+        .insert(ScaledText(REPLAY_FONT_SIZE))
+        .insert(ReplayText);
+}
+
+fn open_pgn_from_args(mut open_evw: EventWriter<OpenPgn>) {
+    if let Some(path) = arg_value("--pgn") {
+        open_evw.send(OpenPgn(path.into()));
+    }
+}
+
+fn open_pgn(
+    mut open_evr: EventReader<OpenPgn>,
+    mut replay: ResMut<Replay>,
+    game: Res<Game>,
+    mut takebacks: ResMut<Takebacks>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    let path = match open_evr.iter().last() {
+        Some(OpenPgn(path)) => path,
+        None => return,
+    };
+    let games = std::fs::read_to_string(path)
+        .map_err(|err| format!("cannot read {}: {}", path.display(), err))
+        .and_then(|text| parse_pgn(&text).map_err(|err| err.to_string()));
+    let games = match games {
+        Ok(games) if games.is_empty() => Err(format!("no games in {}", path.display())),
+        games => games,
+    };
+    match games {
+        Ok(games) => {
+            let count = games.len();
+            // Opening another file keeps the game set aside by the first
+            if replay.live.is_none() {
+                replay.live = Some((game.clone(), std::mem::take(&mut *takebacks)));
+            }
+            replay.games = games;
+            let status = match replay.select_game(0) {
+                Ok(()) => format!("Opened {} game(s)", count),
+                Err(reason) => {
+                    eprintln!("error: {}", reason);
+                    reason
+                }
+            };
+            status_evw.send(StatusMessage(status));
+        }
+        Err(reason) => {
+            eprintln!("error: {}", reason);
+            status_evw.send(StatusMessage(format!("Invalid PGN: {}", reason)));
+        }
+    }
+}
+
+fn replay_keys(
+    keys: Res<Input<KeyCode>>,
+    mut replay: ResMut<Replay>,
+    mut game: ResMut<Game>,
+    mut takebacks: ResMut<Takebacks>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    if !replay.is_active() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        // The clocks run on from where they were
+        if let Some((live, live_takebacks)) = replay.live.take() {
+            *game = live;
+            *takebacks = live_takebacks;
+        }
+        *replay = Replay::default();
+        status_evw.send(StatusMessage("Replay closed".to_string()));
+        return;
+    }
+
+    let count = replay.games.len();
+    let index = if keys.just_pressed(KeyCode::PageDown) {
+        (replay.game + 1) % count
+    } else if keys.just_pressed(KeyCode::PageUp) {
+        (replay.game + count - 1) % count
+    } else {
+        replay.game
+    };
+    if index != replay.game {
+        if let Err(reason) = replay.select_game(index) {
+            eprintln!("error: {}", reason);
+            status_evw.send(StatusMessage(reason));
+        }
+        return;
+    }
+
+    let last = replay.positions.len() - 1;
+    let ply = if keys.just_pressed(KeyCode::Right) {
+        (replay.ply + 1).min(last)
+    } else if keys.just_pressed(KeyCode::Left) {
+        replay.ply.saturating_sub(1)
+    } else if keys.just_pressed(KeyCode::Home) {
+        0
+    } else if keys.just_pressed(KeyCode::End) {
+        last
+    } else {
+        replay.ply
+    };
+    // Only touch the replay when the ply changes, which `show_replay` reacts to
+    if ply != replay.ply {
+        replay.ply = ply;
+    }
+}
+
+fn show_replay(
+    replay: Res<Replay>,
+    mut game: ResMut<Game>,
+    mut takebacks: ResMut<Takebacks>,
+    mut selected_q: Query<&mut SelectingSquares>,
+    mut text_q: Query<&mut Text, With<ReplayText>>,
+) {
+    if !replay.is_changed() {
+        return;
+    }
+    let headers = if replay.is_active() {
+        replay.headers()
+    } else {
+        String::new()
+    };
+    for mut text in text_q.iter_mut() {
+        text.sections[0].value = headers.clone();
+    }
+    if !replay.is_active() {
+        return;
+    }
+
+    // The clocks stay stopped while replaying, and the game played is kept in `live`
+    *game = Game::replayed(
+        replay.positions[0],
+        &replay.records[..replay.ply],
+        game.time_control().clone(),
+    );
+    *takebacks = Takebacks::default();
+
+    let mut selected = selected_q.single_mut();
+    selected.reset();
+    selected.dragging = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Replay;
+    use crate::pgn_import::parse_pgn;
+
+    #[test]
+    fn reports_the_first_unplayable_move() {
+        let games = parse_pgn("1. e4 e5 2. Nf3 *\n\n1. e4 e5 2. Ke3 Nc6 *\n").unwrap();
+        let mut replay = Replay {
+            games,
+            ..Replay::default()
+        };
+
+        assert_eq!(replay.select_game(0), Ok(()));
+        assert_eq!(replay.positions.len(), 4);
+        assert_eq!(replay.records[2].uci, "g1f3");
+
+        assert_eq!(
+            replay.select_game(1),
+            Err("game 2, ply 3: cannot play 'Ke3'".to_string())
+        );
+        // The moves before the bad one can still be replayed
+        assert_eq!(replay.positions.len(), 3);
+    }
+}
@@ -0,0 +1,158 @@
+use chess::{BoardStatus, ChessMove, MoveGen, Piece};
+
+fn piece_letter(piece: Piece) -> &'static str {
+    match piece {
+        Piece::Pawn => "",
+        Piece::Knight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook => "R",
+        Piece::Queen => "Q",
+        Piece::King => "K",
+    }
+}
+
+/// The Standard Algebraic Notation of the legal move `m` on `board`, e.g. `Nbd7`, `exd6`,
+/// `e8=Q+` or `O-O-O#`.
+pub fn to_san(board: &chess::Board, m: ChessMove) -> String {
+    let (source, dest) = (m.get_source(), m.get_dest());
+    let piece = board
+        .piece_on(source)
+        .expect("a legal move starts on a piece");
+
+    let mut san = String::new();
+    let file_distance = source
+        .get_file()
+        .to_index()
+        .abs_diff(dest.get_file().to_index());
+    if piece == Piece::King && file_distance == 2 {
+        san.push_str(
+            if dest.get_file().to_index() > source.get_file().to_index() {
+                "O-O"
+            } else {
+                "O-O-O"
+            },
+        );
+    } else {
+        // A pawn changing file always captures, en passant included
+        let capture =
+            board.piece_on(dest).is_some() || (piece == Piece::Pawn && file_distance != 0);
+        san.push_str(piece_letter(piece));
+        if piece == Piece::Pawn {
+            if capture {
+                san.push_str(&source.to_string()[..1]);
+            }
+        } else {
+            san.push_str(&disambiguation(board, m, piece));
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+        if let Some(promotion) = m.get_promotion() {
+            san.push('=');
+            san.push_str(piece_letter(promotion));
+        }
+    }
+
+    let after = board.make_move_new(m);
+    if after.status() == BoardStatus::Checkmate {
+        san.push('#');
+    } else if after.checkers().popcnt() > 0 {
+        san.push('+');
+    }
+    san
+}
+
+/// The legal move on `board` written as `san`. Check marks and annotations are optional, and
+/// `0-0` castling and promotions without `=` are accepted.
+pub fn from_san(board: &chess::Board, san: &str) -> Option<ChessMove> {
+    let normalize = |san: &str| {
+        san.trim_end_matches(|c: char| "+#!?".contains(c))
+            .replace('0', "O")
+            .replace('=', "")
+    };
+    let wanted = normalize(san);
+    MoveGen::new_legal(board).find(|&m| normalize(&to_san(board, m)) == wanted)
+}
+
+/// The source file, rank or square needed to tell `m` apart from other moves of the same kind
+/// of piece to the same square.
+fn disambiguation(board: &chess::Board, m: ChessMove, piece: Piece) -> String {
+    let source = m.get_source();
+    let rivals: Vec<chess::Square> = MoveGen::new_legal(board)
+        .filter(|other| {
+            other.get_dest() == m.get_dest()
+                && other.get_source() != source
+                && board.piece_on(other.get_source()) == Some(piece)
+        })
+        .map(|other| other.get_source())
+        .collect();
+
+    let square = source.to_string();
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals.iter().all(|sq| sq.get_file() != source.get_file()) {
+        square[..1].to_string()
+    } else if rivals.iter().all(|sq| sq.get_rank() != source.get_rank()) {
+        square[1..].to_string()
+    } else {
+        square
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_san, to_san};
+    use std::str::FromStr;
+
+    fn san(fen: &str, uci: &str) -> String {
+        let board = chess::Board::from_str(fen).unwrap();
+        to_san(&board, chess::ChessMove::from_str(uci).unwrap())
+    }
+
+    #[test]
+    fn writes_plain_moves_and_captures() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+        let en_passant = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        assert_eq!(san(en_passant, "e5f6"), "exf6");
+    }
+
+    #[test]
+    fn disambiguates_by_file_rank_or_square() {
+        let knights = "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1";
+        assert_eq!(san(knights, "b1d2"), "Nbd2");
+        let rooks = "4k3/R7/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san(rooks, "a1a4"), "R1a4");
+        let queens = "1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1";
+        assert_eq!(san(queens, "h4e1"), "Qh4e1");
+    }
+
+    #[test]
+    fn writes_castling_promotion_and_checks() {
+        let castling = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san(castling, "e1g1"), "O-O");
+        assert_eq!(san(castling, "e1c1"), "O-O-O");
+        let promotion = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(promotion, "a7b8q"), "axb8=Q+");
+        assert_eq!(san(promotion, "a7a8n"), "a8=N");
+        let mate = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        assert_eq!(san(mate, "a1a8"), "Ra8#");
+    }
+
+    #[test]
+    fn reads_san() {
+        let board = chess::Board::default();
+        let m = |uci: &str| Some(chess::ChessMove::from_str(uci).unwrap());
+        assert_eq!(from_san(&board, "Nf3"), m("g1f3"));
+        assert_eq!(from_san(&board, "e4!?"), m("e2e4"));
+        assert_eq!(from_san(&board, "Nf4"), None);
+        assert_eq!(from_san(&board, "e5"), None);
+
+        let castling = chess::Board::from_str("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(from_san(&castling, "0-0-0"), m("e1c1"));
+        let promotion = chess::Board::from_str("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(from_san(&promotion, "a8Q+"), m("a7a8q"));
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+use chess::{Board, ChessMove, MoveGen};
+use std::{
+    io::BufRead,
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+};
+
+use crate::{
+    engine::EngineSettings,
+    game::{Game, GameResult},
+    handle_chess_move,
+    replay::Replay,
+    select_move, MoveAttempt, SelectingSquares, SquareComponent,
+};
+
+/// With `--stdin`, plays the moves read from standard input in long algebraic notation
+/// (`e2e4`, `e7e8q`) and answers each, once played or refused, with `legal` or `illegal`
+/// followed by the game status, e.g. `legal check` or `legal 1-0 checkmate`.
+pub struct StdinMovesPlugin;
+
+impl Plugin for StdinMovesPlugin {
+    fn build(&self, app: &mut App) {
+        if std::env::args().any(|arg| arg == "--stdin") {
+            app.insert_resource(read_stdin())
+                .add_system(play_stdin_moves.after(handle_chess_move));
+        }
+    }
+}
+
+/// Lines read from standard input by a separate thread.
+struct StdinLines(Mutex<Receiver<String>>);
+
+fn read_stdin() -> StdinLines {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) if sender.send(line).is_ok() => {}
+                _ => break,
+            }
+        }
+    });
+    StdinLines(Mutex::new(receiver))
+}
+
+/// `ongoing`, `check`, or the result and how it came about, e.g. `1/2-1/2 stalemate`.
+fn game_status(board: &Board, result: Option<GameResult>) -> String {
+    match result.or_else(|| GameResult::from_board(board)) {
+        Some(result) => format!("{} {}", result.tag(), result.reason()),
+        None if board.checkers().popcnt() > 0 => "check".to_string(),
+        None => "ongoing".to_string(),
+    }
+}
+
+/// The move `line` asks for if it is legal on `board`.
+fn legal_move(board: &Board, result: Option<GameResult>, line: &str) -> Option<ChessMove> {
+    ChessMove::from_str(line.trim())
+        .ok()
+        .filter(|&m| result.is_none() && MoveGen::new_legal(board).any(|legal| legal == m))
+}
+
+/// The answer to a move once the game played it or not.
+fn reply(played: bool, game: &Game) -> String {
+    let status = game_status(game.board(), game.result());
+    if played {
+        format!("legal {}", status)
+    } else {
+        format!("illegal {}", status)
+    }
+}
+
+/// Plays one line at a time, answering once `handle_chess_move` played or refused the move.
+fn play_stdin_moves(
+    lines: Res<StdinLines>,
+    engine: Res<EngineSettings>,
+    replay: Res<Replay>,
+    game: Res<Game>,
+    square_q: Query<&SquareComponent>,
+    mut selected_q: Query<&mut SelectingSquares>,
+    mut move_evr: EventReader<MoveAttempt>,
+    mut pending: Local<Option<ChessMove>>,
+) {
+    let mut selected = selected_q.single_mut();
+    if let Some(m) = *pending {
+        let attempt = move_evr.iter().find(|attempt| attempt.m == m);
+        match attempt {
+            Some(attempt) => println!("{}", reply(attempt.result.is_ok(), &game)),
+            // Still waiting for the pieces to land
+            None if selected.end.is_some() => return,
+            // Dropped before it was tried, by a takeback, a replay or a new position
+            None => println!("{}", reply(false, &game)),
+        }
+        *pending = None;
+    } else {
+        // Moves from the board or the engine are not answered
+        for _ in move_evr.iter() {}
+    }
+
+    if selected.end.is_some() || selected.promotion.is_some() {
+        return;
+    }
+    let line = match lines.0.lock().unwrap().try_recv() {
+        Ok(line) if !line.trim().is_empty() => line,
+        _ => return,
+    };
+
+    let board = game.board();
+    // Moves for the engine's side or into a replay are refused like illegal ones
+    let playable = !engine.plays(board) && !replay.is_active();
+    match legal_move(board, game.result(), &line) {
+        Some(m) if playable && select_move(&mut selected, &game, &square_q, m) => {
+            *pending = Some(m);
+        }
+        _ => println!("{}", reply(false, &game)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{legal_move, reply};
+    use crate::fen::parse_fen;
+    use crate::game::{Game, GameEndReason, GameResult};
+    use crate::time_control::TimeControl;
+    use bevy::utils::Duration;
+    use chess::{Board, ChessMove};
+    use std::str::FromStr;
+
+    fn game(fen: &str) -> Game {
+        Game::new(parse_fen(fen).unwrap(), TimeControl::default())
+    }
+
+    #[test]
+    fn reads_legal_moves() {
+        let start = Board::default();
+        let e4 = ChessMove::from_str("e2e4").unwrap();
+        assert_eq!(legal_move(&start, None, "e2e4\n"), Some(e4));
+        assert_eq!(legal_move(&start, None, "e2e5"), None);
+        assert_eq!(legal_move(&start, None, "hello"), None);
+
+        let promotion = Board::from_str("8/4P3/8/8/k7/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(legal_move(&promotion, None, "e7e8"), None);
+        assert!(legal_move(&promotion, None, "e7e8q").is_some());
+
+        let timeout = Some(GameResult::WhiteWins(GameEndReason::Timeout));
+        assert_eq!(legal_move(&start, timeout, "e2e4"), None);
+    }
+
+    #[test]
+    fn answers_with_the_game_status_once_played() {
+        let mut promotion = game("8/4P3/8/8/k7/8/8/4K3 w - - 0 1");
+        assert_eq!(reply(false, &promotion), "illegal ongoing");
+        promotion
+            .try_move(ChessMove::from_str("e7e8q").unwrap())
+            .unwrap();
+        assert_eq!(reply(true, &promotion), "legal check");
+
+        let mut fools_mate = game("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2");
+        fools_mate
+            .try_move(ChessMove::from_str("d8h4").unwrap())
+            .unwrap();
+        assert_eq!(reply(true, &fools_mate), "legal 0-1 checkmate");
+
+        // A flag that fell before the move was played
+        let mut flagged = game("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        flagged.start();
+        flagged.tick(Duration::from_secs(601));
+        let e4 = ChessMove::from_str("e2e4").unwrap();
+        assert!(flagged.try_move(e4).is_err());
+        assert_eq!(reply(false, &flagged), "illegal 0-1 timeout");
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+
+use crate::{
+    ctrl_pressed, engine::EngineSettings, game::Game, replay::Replay, SelectingSquares,
+    StatusMessage,
+};
+
+/// Ctrl+Z takes the last move back and Ctrl+Y (or Ctrl+Shift+Z) plays it again, until the
+/// game is over. With `--confirm-takebacks` the opponent accepts with Y or declines with N
+/// first, unless the opponent is the engine.
+pub struct TakebackPlugin;
+
+impl Plugin for TakebackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Takebacks>()
+            .add_event::<Takeback>()
+            .add_system(takeback_keys)
+            .add_system(apply_takeback.after(takeback_keys));
+    }
+}
+
+pub struct TakebackSettings {
+    /// The opponent has to accept a takeback before it happens.
+    pub confirm: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Takeback {
+    Undo,
+    Redo,
+}
+
+/// The games to go back to and, after a takeback, forward to again.
+#[derive(Default)]
+pub struct Takebacks {
+    undo: Vec<Game>,
+    redo: Vec<Game>,
+}
+
+impl Takebacks {
+    /// Records the game before a new move, which also drops the moves taken back.
+    pub fn push(&mut self, before: Game) {
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    /// The game before the last move, keeping `current` to redo.
+    fn undo(&mut self, current: Game) -> Option<Game> {
+        let game = self.undo.pop()?;
+        self.redo.push(current);
+        Some(game)
+    }
+
+    /// The game after the last move taken back, keeping `current` to undo.
+    fn redo(&mut self, current: Game) -> Option<Game> {
+        let game = self.redo.pop()?;
+        self.undo.push(current);
+        Some(game)
+    }
+}
+
+fn takeback_keys(
+    keys: Res<Input<KeyCode>>,
+    settings: Res<TakebackSettings>,
+    engine: Res<EngineSettings>,
+    replay: Res<Replay>,
+    game: Res<Game>,
+    mut pending: Local<Option<(Takeback, usize)>>,
+    mut takeback_evw: EventWriter<Takeback>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    if replay.is_active() {
+        return;
+    }
+    // A request is about the position it was made in, while the game goes on
+    let moves = game.history().moves.len();
+    if pending.map_or(false, |(_, made_at)| made_at != moves) || game.result().is_some() {
+        *pending = None;
+    }
+
+    if let Some((request, _)) = *pending {
+        if keys.just_pressed(KeyCode::Y) && !ctrl_pressed(&keys) {
+            takeback_evw.send(request);
+            *pending = None;
+        } else if keys.just_pressed(KeyCode::N) {
+            status_evw.send(StatusMessage("Takeback declined".to_string()));
+            *pending = None;
+        }
+        return;
+    }
+
+    if !ctrl_pressed(&keys) {
+        return;
+    }
+    let shift = keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift);
+    let request = if keys.just_pressed(KeyCode::Z) && !shift {
+        Takeback::Undo
+    } else if keys.just_pressed(KeyCode::Y) || keys.just_pressed(KeyCode::Z) {
+        Takeback::Redo
+    } else {
+        return;
+    };
+    // A result, such as a fallen flag, is final
+    if game.result().is_some() {
+        status_evw.send(StatusMessage("Game over".to_string()));
+        return;
+    }
+    if settings.confirm && engine.color.is_none() {
+        *pending = Some((request, moves));
+        status_evw.send(StatusMessage(
+            "Opponent: Y to accept the takeback, N to decline".to_string(),
+        ));
+    } else {
+        takeback_evw.send(request);
+    }
+}
+
+fn apply_takeback(
+    mut takeback_evr: EventReader<Takeback>,
+    mut takebacks: ResMut<Takebacks>,
+    mut game: ResMut<Game>,
+    engine: Res<EngineSettings>,
+    mut selected_q: Query<&mut SelectingSquares>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    let request = match takeback_evr.iter().last() {
+        Some(request) => *request,
+        None => return,
+    };
+
+    let current = game.clone();
+    let restored = match request {
+        Takeback::Undo => takebacks.undo(current),
+        Takeback::Redo => takebacks.redo(current),
+    };
+    let mut restored = match restored {
+        Some(restored) => restored,
+        None => {
+            let status = match request {
+                Takeback::Undo => "No move to take back",
+                Takeback::Redo => "No move to play again",
+            };
+            status_evw.send(StatusMessage(status.to_string()));
+            return;
+        }
+    };
+
+    // Against the engine, go on to a position where the player is to move
+    while engine.plays(restored.board()) {
+        let next = match request {
+            Takeback::Undo => takebacks.undo(restored.clone()),
+            Takeback::Redo => takebacks.redo(restored.clone()),
+        };
+        match next {
+            Some(next) => restored = next,
+            None => break,
+        }
+    }
+
+    // Including the clock times, which run on from where they were if the game was started
+    restored.resume_from(&game);
+    *game = restored;
+
+    let mut selected = selected_q.single_mut();
+    selected.reset();
+    selected.dragging = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Takebacks;
+    use crate::fen::Position;
+    use crate::game::Game;
+    use crate::time_control::TimeControl;
+    use std::str::FromStr;
+
+    fn played(uci_moves: &[&str]) -> Game {
+        let mut game = Game::new(Position::default(), TimeControl::default());
+        for uci in uci_moves {
+            game.try_move(chess::ChessMove::from_str(uci).unwrap())
+                .unwrap();
+        }
+        game
+    }
+
+    fn board(game: Option<Game>) -> Option<chess::Board> {
+        game.map(|game| *game.board())
+    }
+
+    #[test]
+    fn undoes_and_redoes_moves() {
+        let mut takebacks = Takebacks::default();
+        takebacks.push(played(&[]));
+        takebacks.push(played(&["e2e4"]));
+        let current = played(&["e2e4", "e7e5"]);
+
+        let undone = takebacks.undo(current.clone());
+        assert_eq!(board(undone), board(Some(played(&["e2e4"]))));
+        assert_eq!(
+            board(takebacks.redo(played(&["e2e4"]))),
+            board(Some(current))
+        );
+
+        // A new move drops what was taken back
+        takebacks.undo(played(&["e2e4", "e7e5"]));
+        takebacks.push(played(&["e2e4"]));
+        assert!(takebacks.redo(played(&["e2e4", "c7c5"])).is_none());
+        assert_eq!(takebacks.undo.len(), 2);
+    }
+}
@@ -0,0 +1,411 @@
+use bevy::{asset::FileAssetIo, prelude::*};
+use serde::Deserialize;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    arg_value, coordinates::Coordinate, layout::ScaledText, ChessPieceSprites, SelectingEndSquare,
+    SelectingStartSquare, SquareComponent, StatusMessage,
+};
+
+/// Loads the themes in `assets/themes/*.ron`: board and highlight colours, the piece images
+/// and the fonts. `--theme <name>` picks one to start with and T switches to the next.
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Themes::from_args())
+            .add_startup_system_to_stage(StartupStage::PreStartup, load_theme_assets)
+            .add_system(cycle_theme)
+            .add_system(apply_theme.after(cycle_theme));
+    }
+}
+
+/// The pieces image under `assets/`, a grid of tiles in `PieceSprite` order.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PieceAtlas {
+    pub path: String,
+    /// The size of a tile in pixels.
+    pub tile_size: (u32, u32),
+    pub columns: usize,
+    pub rows: usize,
+}
+
+/// A theme as written in its file, with colours as `#rrggbb` or `#rrggbbaa`.
+#[derive(Deserialize)]
+struct ThemeFile {
+    name: String,
+    light_square: String,
+    dark_square: String,
+    selection_start: String,
+    selection_end: String,
+    last_move: String,
+    check: String,
+    move_hint: String,
+    pieces: PieceAtlas,
+    font: String,
+    coordinate_font: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "ThemeFile")]
+pub struct Theme {
+    pub name: String,
+    pub light_square: Color,
+    pub dark_square: Color,
+    pub selection_start: Color,
+    pub selection_end: Color,
+    pub last_move: Color,
+    pub check: Color,
+    pub move_hint: Color,
+    pub pieces: PieceAtlas,
+    /// The font of the side panel, under `assets/`.
+    pub font: String,
+    pub coordinate_font: String,
+}
+
+impl TryFrom<ThemeFile> for Theme {
+    type Error = String;
+
+    fn try_from(file: ThemeFile) -> Result<Self, String> {
+        let color = |hex: &str| {
+            Color::hex(hex.trim_start_matches('#')).map_err(|_| format!("invalid colour '{}'", hex))
+        };
+        Ok(Self {
+            light_square: color(&file.light_square)?,
+            dark_square: color(&file.dark_square)?,
+            selection_start: color(&file.selection_start)?,
+            selection_end: color(&file.selection_end)?,
+            last_move: color(&file.last_move)?,
+            check: color(&file.check)?,
+            move_hint: color(&file.move_hint)?,
+            name: file.name,
+            pieces: file.pieces,
+            font: file.font,
+            coordinate_font: file.coordinate_font,
+        })
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        parse_theme(include_str!("../assets/themes/classic.ron")).unwrap()
+    }
+}
+
+impl Theme {
+    pub fn square_color(&self, sq: chess::Square) -> Color {
+        if is_dark_square(sq) {
+            self.dark_square
+        } else {
+            self.light_square
+        }
+    }
+}
+
+/// a1 is dark, and so is every square of the same colour.
+pub fn is_dark_square(sq: chess::Square) -> bool {
+    (sq.get_file().to_index() + sq.get_rank().to_index()) % 2 == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThemeError {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "theme {}: {}", self.path.display(), self.reason)
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+pub fn parse_theme(text: &str) -> Result<Theme, String> {
+    ron::de::from_str(text).map_err(|err| err.to_string())
+}
+
+/// Every theme in `dir`, by file name, with the errors of those that could not be read.
+fn read_themes(dir: &Path) -> (Vec<Theme>, Vec<ThemeError>) {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "ron"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+
+    let mut themes = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        let theme = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| parse_theme(&text));
+        match theme {
+            Ok(theme) => themes.push(theme),
+            Err(reason) => errors.push(ThemeError { path, reason }),
+        }
+    }
+    (themes, errors)
+}
+
+/// The themes to choose from and the one in use.
+pub struct Themes {
+    themes: Vec<Theme>,
+    current: usize,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Self {
+            themes: vec![Theme::default()],
+            current: 0,
+        }
+    }
+}
+
+impl Themes {
+    fn from_args() -> Self {
+        let dir = FileAssetIo::get_root_path().join("assets").join("themes");
+        let (themes, errors) = read_themes(&dir);
+        for error in errors {
+            eprintln!("warning: {}", error);
+        }
+        if themes.is_empty() {
+            return Self::default();
+        }
+
+        let current = match arg_value("--theme") {
+            Some(name) => match themes
+                .iter()
+                .position(|t| t.name.eq_ignore_ascii_case(&name))
+            {
+                Some(i) => i,
+                None => {
+                    eprintln!("warning: no theme named '{}'", name);
+                    0
+                }
+            },
+            None => 0,
+        };
+        Self { themes, current }
+    }
+
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+
+    /// Switches to the next theme, wrapping around to the first.
+    fn next(&mut self) {
+        self.current = (self.current + 1) % self.themes.len();
+    }
+}
+
+/// The fonts of the current theme.
+pub struct ThemeFonts {
+    pub font: Handle<Font>,
+    pub coordinate_font: Handle<Font>,
+}
+
+impl ThemeFonts {
+    fn load(asset_server: &AssetServer, theme: &Theme) -> Self {
+        Self {
+            font: asset_server.load(theme.font.as_str()),
+            coordinate_font: asset_server.load(theme.coordinate_font.as_str()),
+        }
+    }
+}
+
+fn piece_atlas(asset_server: &AssetServer, atlas: &PieceAtlas) -> TextureAtlas {
+    let image = asset_server.load(atlas.path.as_str());
+    let (width, height) = atlas.tile_size;
+    let tile_size = Vec2::new(width as f32, height as f32);
+    TextureAtlas::from_grid(image, tile_size, atlas.columns, atlas.rows)
+}
+
+fn load_theme_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    themes: Res<Themes>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let theme = themes.current();
+    let texture_atlas = piece_atlas(&asset_server, &theme.pieces);
+    commands.insert_resource(ChessPieceSprites(texture_atlases.add(texture_atlas)));
+    commands.insert_resource(ThemeFonts::load(&asset_server, theme));
+}
+
+fn cycle_theme(
+    keys: Res<Input<KeyCode>>,
+    mut themes: ResMut<Themes>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    if !keys.just_pressed(KeyCode::T) || themes.themes.len() < 2 {
+        return;
+    }
+    themes.next();
+    status_evw.send(StatusMessage(format!("Theme: {}", themes.current().name)));
+}
+
+/// Swaps the pieces and fonts in use for those of the new theme and recolours the board;
+/// the highlights, coordinates and move hints recolour themselves.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_theme(
+    asset_server: Res<AssetServer>,
+    themes: Res<Themes>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut pieces: ResMut<ChessPieceSprites>,
+    mut fonts: ResMut<ThemeFonts>,
+    mut atlas_q: Query<&mut Handle<TextureAtlas>>,
+    mut coordinate_q: Query<&mut Text, With<Coordinate>>,
+    mut panel_q: Query<&mut Text, (With<ScaledText>, Without<Coordinate>)>,
+    mut square_q: Query<(&SquareComponent, &mut Sprite)>,
+    mut selection_q: Query<
+        (&mut Sprite, Option<&SelectingStartSquare>),
+        (
+            Or<(With<SelectingStartSquare>, With<SelectingEndSquare>)>,
+            Without<SquareComponent>,
+        ),
+    >,
+) {
+    if !themes.is_changed() || themes.is_added() {
+        return;
+    }
+    let theme = themes.current();
+
+    let texture_atlas = piece_atlas(&asset_server, &theme.pieces);
+    let old_atlas = std::mem::replace(&mut pieces.0, texture_atlases.add(texture_atlas));
+    for mut atlas in atlas_q.iter_mut() {
+        if *atlas == old_atlas {
+            *atlas = pieces.0.clone();
+        }
+    }
+    texture_atlases.remove(&old_atlas);
+
+    // By role, as two themes may share a font for both
+    *fonts = ThemeFonts::load(&asset_server, theme);
+    for mut text in coordinate_q.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.font = fonts.coordinate_font.clone();
+        }
+    }
+    for mut text in panel_q.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.font = fonts.font.clone();
+        }
+    }
+
+    for (square, mut sprite) in square_q.iter_mut() {
+        sprite.color = theme.square_color(square.chess_sq);
+    }
+    for (mut sprite, start) in selection_q.iter_mut() {
+        sprite.color = if start.is_some() {
+            theme.selection_start
+        } else {
+            theme.selection_end
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_theme, is_dark_square, parse_theme, read_themes, Theme, ThemeFonts, Themes};
+    use crate::{coordinates::Coordinate, layout::ScaledText, ChessPieceSprites};
+    use bevy::{asset::AssetPlugin, prelude::*};
+    use chess::Square;
+    use std::path::Path;
+
+    #[test]
+    fn reads_the_bundled_themes() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/themes");
+        let (themes, errors) = read_themes(&dir);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(themes[0], Theme::default());
+        assert!(themes.len() > 1);
+        assert!(themes.iter().any(|t| t.pieces != themes[0].pieces));
+
+        let classic = Theme::default();
+        assert_eq!(classic.name, "Classic");
+        assert_eq!(classic.light_square, Color::rgb_u8(240, 217, 181));
+        assert_eq!(classic.last_move, Color::rgba_u8(242, 217, 64, 115));
+        assert_eq!(classic.pieces.tile_size, (106, 106));
+        assert_eq!(classic.square_color(Square::A1), classic.dark_square);
+        assert!(!is_dark_square(Square::H1));
+    }
+
+    #[test]
+    fn rejects_invalid_colours() {
+        let classic = include_str!("../assets/themes/classic.ron");
+        let invalid = classic.replace("#f0d9b5", "#f0d9b");
+        assert!(parse_theme(&invalid)
+            .unwrap_err()
+            .contains("invalid colour '#f0d9b'"));
+        assert!(parse_theme("(name: \"Empty\")").is_err());
+    }
+
+    #[test]
+    fn gives_each_text_the_font_of_its_role() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/themes");
+        let (themes, _) = read_themes(&dir);
+        let ocean = themes.iter().position(|t| t.name == "Ocean").unwrap();
+        assert_eq!(themes[ocean + 1].name, "Tournament");
+        // Ocean uses one font for both roles
+        assert_eq!(themes[ocean].font, themes[ocean].coordinate_font);
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<TextureAtlas>()
+            .insert_resource(Themes {
+                themes,
+                current: ocean,
+            })
+            .insert_resource(ChessPieceSprites(Handle::default()))
+            .add_system(apply_theme);
+        let asset_server = app.world.resource::<AssetServer>().clone();
+        let fonts = ThemeFonts::load(&asset_server, app.world.resource::<Themes>().current());
+        let coordinate = app
+            .world
+            .spawn()
+            .insert(Coordinate::File(chess::File::A))
+            .insert(ScaledText(12.))
+            .insert(Text::from_section(
+                "a",
+                TextStyle {
+                    font: fonts.coordinate_font.clone(),
+                    ..default()
+                },
+            ))
+            .id();
+        let panel = app
+            .world
+            .spawn()
+            .insert(ScaledText(14.))
+            .insert(Text::from_section(
+                "1. e4",
+                TextStyle {
+                    font: fonts.font.clone(),
+                    ..default()
+                },
+            ))
+            .id();
+        app.insert_resource(fonts);
+        app.update();
+
+        app.world.resource_mut::<Themes>().next();
+        app.update();
+        let font = |entity: Entity| {
+            app.world.get::<Text>(entity).unwrap().sections[0]
+                .style
+                .font
+                .clone()
+        };
+        let handle = |path: &str| asset_server.get_handle::<Font, _>(path);
+        assert_eq!(font(coordinate), handle("fonts/FiraMono-Bold.ttf"));
+        assert_eq!(font(panel), handle("fonts/FiraMono-Medium.ttf"));
+    }
+}
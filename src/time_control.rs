@@ -0,0 +1,167 @@
+use bevy::utils::Duration;
+
+/// How a side is compensated for the time spent on each move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Increment {
+    None,
+    /// Fischer: the increment is added after every move.
+    Fischer(Duration),
+    /// Bronstein: the time used on a move is given back, up to the delay.
+    Bronstein(Duration),
+    /// Simple (US) delay: the clock waits for the delay before counting down.
+    Simple(Duration),
+}
+
+/// One period of a time control, e.g. "40 moves in 90 minutes".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeStage {
+    /// Moves to be played within the stage, `None` for the rest of the game.
+    pub moves: Option<u32>,
+    /// Time added to the clock when the stage begins.
+    pub base: Duration,
+    pub increment: Increment,
+}
+
+impl TimeStage {
+    pub fn sudden_death(base: Duration, increment: Increment) -> Self {
+        Self {
+            moves: None,
+            base,
+            increment,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub name: String,
+    pub white: Vec<TimeStage>,
+    pub black: Vec<TimeStage>,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self::sudden_death("10 min", minutes(10), Increment::None)
+    }
+}
+
+impl TimeControl {
+    /// The same single stage for both sides.
+    pub fn sudden_death(name: &str, base: Duration, increment: Increment) -> Self {
+        let stage = TimeStage::sudden_death(base, increment);
+        Self {
+            name: name.to_string(),
+            white: vec![stage.clone()],
+            black: vec![stage],
+        }
+    }
+
+    /// The time controls offered before the game starts.
+    pub fn presets() -> Vec<Self> {
+        let classical = vec![
+            TimeStage {
+                moves: Some(40),
+                base: minutes(90),
+                increment: Increment::Fischer(seconds(30)),
+            },
+            TimeStage::sudden_death(minutes(30), Increment::Fischer(seconds(30))),
+        ];
+        vec![
+            Self::default(),
+            Self::sudden_death("5 + 3", minutes(5), Increment::Fischer(seconds(3))),
+            Self::sudden_death("3 + 2", minutes(3), Increment::Fischer(seconds(2))),
+            Self::sudden_death("15 + 10", minutes(15), Increment::Fischer(seconds(10))),
+            Self::sudden_death("5 d5", minutes(5), Increment::Simple(seconds(5))),
+            Self::sudden_death("5 br3", minutes(5), Increment::Bronstein(seconds(3))),
+            Self {
+                name: "40/90+30".to_string(),
+                white: classical.clone(),
+                black: classical,
+            },
+            Self {
+                name: "10 vs 5".to_string(),
+                white: vec![TimeStage::sudden_death(minutes(10), Increment::None)],
+                black: vec![TimeStage::sudden_death(minutes(5), Increment::None)],
+            },
+        ]
+    }
+
+    pub fn stages(&self, color: chess::Color) -> &[TimeStage] {
+        match color {
+            chess::Color::White => &self.white,
+            chess::Color::Black => &self.black,
+        }
+    }
+
+    /// The stage in effect for a side that has completed `moves` moves.
+    pub fn stage(&self, color: chess::Color, moves: u32) -> &TimeStage {
+        let stages = self.stages(color);
+        let mut played = 0;
+        for stage in stages {
+            match stage.moves {
+                Some(n) if moves >= played + n => played += n,
+                _ => return stage,
+            }
+        }
+        stages
+            .last()
+            .expect("a time control has at least one stage")
+    }
+
+    /// The stage that begins once a side has completed exactly `moves` moves.
+    pub fn stage_starting_after(&self, color: chess::Color, moves: u32) -> Option<&TimeStage> {
+        let stages = self.stages(color);
+        let mut played = 0;
+        for (i, stage) in stages.iter().enumerate() {
+            played += stage.moves?;
+            if played == moves {
+                return stages.get(i + 1);
+            }
+        }
+        None
+    }
+}
+
+fn minutes(n: u64) -> Duration {
+    Duration::from_secs(60 * n)
+}
+
+fn seconds(n: u64) -> Duration {
+    Duration::from_secs(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{minutes, seconds, Increment, TimeControl};
+    use chess::Color;
+
+    fn classical() -> TimeControl {
+        TimeControl::presets()
+            .into_iter()
+            .find(|tc| tc.name == "40/90+30")
+            .unwrap()
+    }
+
+    #[test]
+    fn rolls_into_the_next_stage_after_its_moves() {
+        let tc = classical();
+        assert_eq!(tc.stage(Color::White, 0).base, minutes(90));
+        assert_eq!(tc.stage(Color::White, 39).base, minutes(90));
+        assert_eq!(tc.stage(Color::White, 40).base, minutes(30));
+        assert_eq!(tc.stage(Color::Black, 75).base, minutes(30));
+
+        assert_eq!(
+            tc.stage_starting_after(Color::White, 40).map(|s| s.base),
+            Some(minutes(30))
+        );
+        assert_eq!(tc.stage_starting_after(Color::White, 39), None);
+        assert_eq!(tc.stage_starting_after(Color::White, 41), None);
+    }
+
+    #[test]
+    fn sudden_death_has_a_single_stage() {
+        let tc = TimeControl::sudden_death("5 br3", minutes(5), Increment::Bronstein(seconds(3)));
+        assert_eq!(tc.stage(Color::Black, 100).base, minutes(5));
+        assert_eq!(tc.stage_starting_after(Color::Black, 0), None);
+    }
+}
@@ -0,0 +1,677 @@
+use bevy::prelude::*;
+use chess::{Board, ChessMove, MoveGen};
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    arg_value,
+    engine::EngineSettings,
+    game::Game,
+    history::MoveHistory,
+    layout::{BoardLayout, PanelItem, PanelSlot, ScaledText},
+    replay::Replay,
+    san::to_san,
+    select_move,
+    theme::ThemeFonts,
+    BoardOrientation, SelectingSquares, SquareComponent, StatusMessage, FONT_COLOR,
+};
+
+const ANALYSIS_FONT_SIZE: f32 = 10.0;
+/// How long the engine may take to answer `uci` and `isready`, both together.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Moves of the principal variation shown in the analysis.
+const ANALYSIS_MOVES: usize = 3;
+
+/// Runs an external UCI engine given with `--uci <path>`, configured with any number of
+/// `--uci-option "Name=Value"`. It plays the side chosen for the built-in engine, or with
+/// `--uci-analysis` analyses every position in the side panel instead. The engine starts on
+/// its own thread, so the window runs on while it gets ready.
+pub struct UciPlugin;
+
+impl Plugin for UciPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(start_uci_engine)
+            .add_system(connect_uci_engine)
+            .add_system(uci_engine.after(connect_uci_engine));
+    }
+}
+
+/// A line from the engine, as far as the GUI cares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciMessage {
+    Name(String),
+    UciOk,
+    ReadyOk,
+    /// `None` when the engine has no move, being mated or stalemated.
+    BestMove(Option<ChessMove>),
+    Info(UciInfo),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UciInfo {
+    pub depth: Option<u32>,
+    pub score: Option<Score>,
+    pub nodes: Option<u64>,
+    /// The principal variation.
+    pub pv: Vec<ChessMove>,
+    pub string: Option<String>,
+}
+
+/// An engine's score, for the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mate in that many moves, negative when the side to move gets mated.
+    Mate(i32),
+}
+
+/// Parses a line sent by the engine, `None` for lines the GUI does not use.
+pub fn parse_line(line: &str) -> Option<UciMessage> {
+    let mut tokens = line.split_whitespace();
+    match tokens.next()? {
+        "id" if tokens.next() == Some("name") => {
+            Some(UciMessage::Name(tokens.collect::<Vec<_>>().join(" ")))
+        }
+        "uciok" => Some(UciMessage::UciOk),
+        "readyok" => Some(UciMessage::ReadyOk),
+        "bestmove" => {
+            let best_move = tokens.next().and_then(|m| ChessMove::from_str(m).ok());
+            Some(UciMessage::BestMove(best_move))
+        }
+        "info" => Some(UciMessage::Info(parse_info(tokens.collect()))),
+        _ => None,
+    }
+}
+
+fn parse_info(tokens: Vec<&str>) -> UciInfo {
+    let mut info = UciInfo::default();
+    let mut i = 0;
+    while i < tokens.len() {
+        let value = tokens.get(i + 1).copied().unwrap_or_default();
+        match tokens[i] {
+            "depth" => info.depth = value.parse().ok(),
+            "nodes" => info.nodes = value.parse().ok(),
+            "score" => {
+                let score = tokens.get(i + 2).and_then(|score| score.parse().ok());
+                info.score = match value {
+                    "cp" => score.map(Score::Centipawns),
+                    "mate" => score.map(Score::Mate),
+                    _ => None,
+                };
+                i += 1;
+            }
+            "pv" => {
+                info.pv = tokens[i + 1..]
+                    .iter()
+                    .map_while(|m| ChessMove::from_str(m).ok())
+                    .collect();
+                i += 1 + info.pv.len();
+                continue;
+            }
+            // The rest of the line is free text
+            "string" => {
+                info.string = Some(tokens[i + 1..].join(" "));
+                break;
+            }
+            // Every other field has a single value, apart from the bounds after a score
+            "lowerbound" | "upperbound" => {
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        i += 2;
+    }
+    info
+}
+
+/// The `position` command for the current game: the start of the history and the moves
+/// played since.
+pub fn position_command(history: &MoveHistory) -> String {
+    let mut command = format!("position fen {}", history.start);
+    if !history.moves.is_empty() {
+        command.push_str(" moves");
+        for record in &history.moves {
+            command.push(' ');
+            command.push_str(&record.uci);
+        }
+    }
+    command
+}
+
+/// Why the engine could not be used.
+#[derive(Debug)]
+pub enum UciError {
+    Io(io::Error),
+    Timeout(&'static str),
+    Exited,
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UciError::Io(err) => write!(f, "engine I/O failed: {}", err),
+            UciError::Timeout(expected) => write!(f, "engine did not send {}", expected),
+            UciError::Exited => write!(f, "engine exited"),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+impl From<io::Error> for UciError {
+    fn from(err: io::Error) -> Self {
+        UciError::Io(err)
+    }
+}
+
+/// A running engine process.
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines read from the engine's output by a separate thread.
+    lines: Mutex<Receiver<String>>,
+    pub name: String,
+}
+
+impl UciEngine {
+    /// Starts the engine at `path` and sets its `options` once it is ready, which it has to be
+    /// within `timeout`. Blocks until then.
+    pub fn spawn(
+        path: &Path,
+        options: &[(String, Option<String>)],
+        timeout: Duration,
+    ) -> Result<Self, UciError> {
+        let deadline = Instant::now() + timeout;
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or(UciError::Exited)?;
+        let stdout = child.stdout.take().ok_or(UciError::Exited)?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) if sender.send(line).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+
+        let mut engine = Self {
+            child,
+            stdin,
+            lines: Mutex::new(receiver),
+            name: path.display().to_string(),
+        };
+        engine.send("uci")?;
+        loop {
+            match engine.recv(deadline, "uciok")? {
+                UciMessage::Name(name) => engine.name = name,
+                UciMessage::UciOk => break,
+                _ => {}
+            }
+        }
+        for (name, value) in options {
+            let command = match value {
+                Some(value) => format!("setoption name {} value {}", name, value),
+                None => format!("setoption name {}", name),
+            };
+            engine.send(&command)?;
+        }
+        engine.send("isready")?;
+        while engine.recv(deadline, "readyok")? != UciMessage::ReadyOk {}
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), UciError> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Waits for the next message the GUI uses, until `deadline` at the latest.
+    pub fn recv(&self, deadline: Instant, expected: &'static str) -> Result<UciMessage, UciError> {
+        let lines = self.lines.lock().unwrap();
+        loop {
+            match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => {
+                    if let Some(message) = parse_line(&line) {
+                        return Ok(message);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(UciError::Timeout(expected)),
+                Err(RecvTimeoutError::Disconnected) => return Err(UciError::Exited),
+            }
+        }
+    }
+
+    /// The messages sent since the last poll, without waiting.
+    pub fn poll(&self) -> Result<Vec<UciMessage>, UciError> {
+        let lines = self.lines.lock().unwrap();
+        let mut messages = Vec::new();
+        loop {
+            match lines.try_recv() {
+                Ok(line) => messages.extend(parse_line(&line)),
+                Err(TryRecvError::Empty) => return Ok(messages),
+                // Still hand over what came before the engine went away
+                Err(TryRecvError::Disconnected) if !messages.is_empty() => return Ok(messages),
+                Err(TryRecvError::Disconnected) => return Err(UciError::Exited),
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        for _ in 0..10 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct UciSettings {
+    path: PathBuf,
+    options: Vec<(String, Option<String>)>,
+    analysis: bool,
+}
+
+impl UciSettings {
+    fn from_args() -> Option<Self> {
+        let path = arg_value("--uci")?.into();
+        let args: Vec<String> = std::env::args().collect();
+        let options = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--uci-option")
+            .map(|pair| match pair[1].split_once('=') {
+                Some((name, value)) => (name.trim().to_string(), Some(value.trim().to_string())),
+                None => (pair[1].trim().to_string(), None),
+            })
+            .collect();
+        Some(Self {
+            path,
+            options,
+            analysis: args.iter().any(|arg| arg == "--uci-analysis"),
+        })
+    }
+}
+
+/// The engine getting ready on its own thread.
+struct UciStartup {
+    settings: UciSettings,
+    engine: Mutex<Receiver<Result<UciEngine, UciError>>>,
+}
+
+/// The engine and what it is searching.
+struct UciClient {
+    engine: UciEngine,
+    analysis: bool,
+    /// The hash of the position last searched.
+    searched: Option<u64>,
+    searching: bool,
+    /// Searches stopped whose `bestmove` is still to come.
+    stopped: usize,
+}
+
+impl UciClient {
+    fn go(&mut self, game: &Game, go: &str) -> Result<(), UciError> {
+        self.engine.send(&position_command(game.history()))?;
+        self.engine.send(go)?;
+        self.searched = Some(game.board().get_hash());
+        self.searching = true;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), UciError> {
+        if self.searching {
+            self.engine.send("stop")?;
+            self.searching = false;
+            self.stopped += 1;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Component)]
+struct AnalysisText;
+
+fn start_uci_engine(mut commands: Commands, mut engine_settings: ResMut<EngineSettings>) {
+    let settings = match UciSettings::from_args() {
+        Some(settings) => settings,
+        None => return,
+    };
+    let (sender, receiver) = mpsc::channel();
+    let (path, options) = (settings.path.clone(), settings.options.clone());
+    std::thread::spawn(move || {
+        let _ = sender.send(UciEngine::spawn(&path, &options, HANDSHAKE_TIMEOUT));
+    });
+    // The built-in engine leaves the side to it while it gets ready
+    engine_settings.external = !settings.analysis;
+    commands.insert_resource(UciStartup {
+        settings,
+        engine: Mutex::new(receiver),
+    });
+}
+
+/// Takes the engine on once it is ready, or hands its side back to the built-in engine.
+fn connect_uci_engine(
+    mut commands: Commands,
+    startup: Option<Res<UciStartup>>,
+    fonts: Res<ThemeFonts>,
+    layout: Res<BoardLayout>,
+    orientation: Res<BoardOrientation>,
+    mut engine_settings: ResMut<EngineSettings>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    let startup = match startup {
+        Some(startup) => startup,
+        None => return,
+    };
+    let engine = match startup.engine.lock().unwrap().try_recv() {
+        Ok(engine) => engine,
+        Err(TryRecvError::Empty) => return,
+        Err(TryRecvError::Disconnected) => Err(UciError::Exited),
+    };
+    commands.remove_resource::<UciStartup>();
+    let settings = &startup.settings;
+    let engine = match engine {
+        Ok(engine) => engine,
+        Err(err) => {
+            let status = format!("cannot start {}: {}", settings.path.display(), err);
+            eprintln!("error: {}", status);
+            status_evw.send(StatusMessage(status));
+            engine_settings.external = false;
+            return;
+        }
+    };
+    status_evw.send(StatusMessage(format!("Engine: {}", engine.name)));
+
+    if settings.analysis {
+        let item = PanelItem::stacked(PanelSlot::Analysis);
+        let (position, size) = layout.place(&item, *orientation);
+        commands
+            .spawn_bundle(
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: fonts.font.clone(),
+                        font_size: ANALYSIS_FONT_SIZE * layout.scale,
+                        color: FONT_COLOR,
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    position,
+                    size,
+                    ..default()
+                }),
+            )
+            .insert(Name::new("AnalysisText"))
+            .insert(item)
+            .insert(ScaledText(ANALYSIS_FONT_SIZE))
+            .insert(AnalysisText);
+    }
+    commands.insert_resource(UciClient {
+        engine,
+        analysis: settings.analysis,
+        searched: None,
+        searching: false,
+        stopped: 0,
+    });
+}
+
+/// The score from White's side and the start of the principal variation, e.g. `+0.25 d12`
+/// over `e4 e5 Nf3`.
+fn describe(info: &UciInfo, board: &Board) -> String {
+    let white = if board.side_to_move() == chess::Color::White {
+        1
+    } else {
+        -1
+    };
+    let score = match info.score {
+        Some(Score::Centipawns(cp)) => format!("{:+.2}", (cp * white) as f32 / 100.),
+        Some(Score::Mate(moves)) => format!("#{}", moves * white),
+        None => String::new(),
+    };
+    let depth = info.depth.map(|depth| format!(" d{}", depth));
+
+    let mut board = *board;
+    let mut sans = Vec::new();
+    for &m in info.pv.iter().take(ANALYSIS_MOVES) {
+        if !MoveGen::new_legal(&board).any(|legal| legal == m) {
+            break;
+        }
+        sans.push(to_san(&board, m));
+        board = board.make_move_new(m);
+    }
+    format!("{}{}\n{}", score, depth.unwrap_or_default(), sans.join(" "))
+}
+
+/// Plays the engine's moves like a player would, or shows its analysis of the position.
+#[allow(clippy::too_many_arguments)]
+fn uci_engine(
+    mut commands: Commands,
+    client: Option<ResMut<UciClient>>,
+    mut settings: ResMut<EngineSettings>,
+    replay: Res<Replay>,
+    game: Res<Game>,
+    square_q: Query<&SquareComponent>,
+    mut selected_q: Query<&mut SelectingSquares>,
+    mut analysis_q: Query<&mut Text, With<AnalysisText>>,
+    mut status_evw: EventWriter<StatusMessage>,
+) {
+    let mut client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    let board = *game.board();
+    let mut selected = selected_q.single_mut();
+
+    let result = client.engine.poll().and_then(|messages| {
+        for message in messages {
+            match message {
+                // The answer to a search given up on
+                UciMessage::BestMove(_) if client.stopped > 0 => client.stopped -= 1,
+                UciMessage::BestMove(best_move) => {
+                    client.searching = false;
+                    let current = client.searched == Some(board.get_hash());
+                    if client.analysis || !current || !settings.plays(&board) {
+                        continue;
+                    }
+                    match best_move {
+                        // Search again should the position come back after a takeback
+                        Some(m) if select_move(&mut selected, &game, &square_q, m) => {
+                            client.searched = None
+                        }
+                        _ => status_evw.send(StatusMessage(format!(
+                            "{} has no legal move to play",
+                            client.engine.name
+                        ))),
+                    }
+                }
+                UciMessage::Info(info) if client.analysis && client.stopped == 0 => {
+                    if info.score.is_some() || !info.pv.is_empty() {
+                        for mut text in analysis_q.iter_mut() {
+                            text.sections[0].value = describe(&info, &board);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let hash = board.get_hash();
+        if client.searching && client.searched != Some(hash) {
+            // The position changed under the search, by a takeback for instance
+            client.stop()?;
+        }
+        if client.searching || client.stopped > 0 || client.searched == Some(hash) {
+            return Ok(());
+        }
+        if client.analysis {
+            for mut text in analysis_q.iter_mut() {
+                text.sections[0].value.clear();
+            }
+            return client.go(&game, "go infinite");
+        }
+        let waiting = selected.end.is_some() || selected.promotion.is_some();
+        if settings.plays(&board) && game.result().is_none() && !replay.is_active() && !waiting {
+            let limits = settings.limits_for(&game);
+            let go = format!(
+                "go depth {} movetime {}",
+                limits.max_depth,
+                limits.time.as_millis()
+            );
+            return client.go(&game, &go);
+        }
+        Ok(())
+    });
+
+    if let Err(err) = result {
+        // The built-in engine takes over
+        status_evw.send(StatusMessage(format!("{}: {}", client.engine.name, err)));
+        settings.external = false;
+        commands.remove_resource::<UciClient>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_line, position_command, Score, UciEngine, UciError, UciInfo, UciMessage};
+    use crate::fen::Position;
+    use crate::history::{MoveHistory, MoveRecord};
+    use bevy::utils::Duration;
+    use chess::ChessMove;
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::time::Instant;
+
+    fn uci(m: &str) -> ChessMove {
+        ChessMove::from_str(m).unwrap()
+    }
+
+    #[test]
+    fn parses_engine_output() {
+        assert_eq!(
+            parse_line("id name Stockfish 15"),
+            Some(UciMessage::Name("Stockfish 15".to_string()))
+        );
+        assert_eq!(parse_line("id author nobody"), None);
+        assert_eq!(parse_line("uciok"), Some(UciMessage::UciOk));
+        assert_eq!(
+            parse_line("bestmove e7e8q ponder a2a3"),
+            Some(UciMessage::BestMove(Some(uci("e7e8q"))))
+        );
+        assert_eq!(
+            parse_line("bestmove (none)"),
+            Some(UciMessage::BestMove(None))
+        );
+        assert_eq!(
+            parse_line(
+                "info depth 12 seldepth 15 multipv 1 score cp -31 upperbound nodes 4096 \
+                 nps 800000 time 5 pv e2e4 e7e5 g1f3"
+            ),
+            Some(UciMessage::Info(UciInfo {
+                depth: Some(12),
+                score: Some(Score::Centipawns(-31)),
+                nodes: Some(4096),
+                pv: vec![uci("e2e4"), uci("e7e5"), uci("g1f3")],
+                string: None,
+            }))
+        );
+        assert_eq!(
+            parse_line("info score mate -3 string mated soon"),
+            Some(UciMessage::Info(UciInfo {
+                score: Some(Score::Mate(-3)),
+                string: Some("mated soon".to_string()),
+                ..UciInfo::default()
+            }))
+        );
+    }
+
+    #[test]
+    fn builds_the_position_from_the_history() {
+        let mut history = MoveHistory::new(Position::default());
+        assert_eq!(
+            position_command(&history),
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        for m in ["e2e4", "e7e5"] {
+            history.moves.push(MoveRecord {
+                san: String::new(),
+                uci: m.to_string(),
+                fen_before: String::new(),
+                white_clock: Duration::ZERO,
+                black_clock: Duration::ZERO,
+            });
+        }
+        assert!(position_command(&history).ends_with(" w KQkq - 0 1 moves e2e4 e7e5"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn talks_to_a_scripted_engine() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fake-uci-engine.sh");
+        let options = [("Skill Level".to_string(), Some("3".to_string()))];
+        let timeout = Duration::from_secs(10);
+        let mut engine = UciEngine::spawn(Path::new(path), &options, timeout).unwrap();
+        assert_eq!(engine.name, "Fake Engine");
+
+        let mut search = |commands: &[&str]| {
+            for command in commands {
+                engine.send(command).unwrap();
+            }
+            let mut strings = Vec::new();
+            loop {
+                match engine.recv(Instant::now() + timeout, "bestmove").unwrap() {
+                    UciMessage::Info(UciInfo {
+                        string: Some(string),
+                        ..
+                    }) => strings.push(string),
+                    UciMessage::BestMove(best_move) => return (strings, best_move),
+                    _ => {}
+                }
+            }
+        };
+
+        let start = "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let (strings, best_move) = search(&[start, "go movetime 100"]);
+        assert_eq!(strings, [start, "skill 3"]);
+        assert_eq!(best_move, Some(uci("e2e4")));
+
+        let after_e4 = format!("{} moves e2e4", start);
+        let (strings, best_move) = search(&[after_e4.as_str(), "go infinite", "stop"]);
+        assert_eq!(strings[0], after_e4);
+        assert_eq!(best_move, Some(uci("e7e5")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn gives_up_on_an_engine_that_never_gets_ready() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/chatty-uci-engine.sh");
+        let started = Instant::now();
+        let timeout = Duration::from_millis(500);
+        let result = UciEngine::spawn(Path::new(path), &[], timeout);
+        assert!(matches!(result, Err(UciError::Timeout("readyok"))));
+        // However much it says while it waits
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
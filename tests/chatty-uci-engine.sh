@@ -0,0 +1,11 @@
+#!/bin/sh
+# A UCI engine that answers `uci` but never `isready`, printing `info` lines all the while,
+# used by the tests in src/uci.rs.
+
+read -r line
+echo "id name Chatty Engine"
+echo "uciok"
+while true; do
+    echo "info string still thinking"
+    sleep 0.05
+done
@@ -0,0 +1,48 @@
+#!/bin/sh
+# A scripted stand-in for a UCI engine, used by the tests in src/uci.rs. It echoes what it
+# was told in `info string` lines and answers 1. e4 e5 regardless of strength.
+
+position=""
+skill=""
+searching=""
+while read -r line; do
+    case "$line" in
+    uci)
+        echo "id name Fake Engine"
+        echo "id author chess_bevy"
+        echo "option name Skill Level type spin default 20 min 0 max 20"
+        echo "uciok"
+        ;;
+    isready)
+        echo "readyok"
+        ;;
+    "setoption name Skill Level value "*)
+        skill="${line#setoption name Skill Level value }"
+        ;;
+    position*)
+        position="$line"
+        ;;
+    go*)
+        case "$position" in
+        *" moves e2e4") move="e7e5" ;;
+        *) move="e2e4" ;;
+        esac
+        echo "info string $position"
+        echo "info string skill $skill"
+        echo "info depth 2 seldepth 3 score cp 25 nodes 120 nps 1000 time 5 pv $move"
+        case "$line" in
+        "go infinite"*) searching="$move" ;;
+        *) echo "bestmove $move" ;;
+        esac
+        ;;
+    stop)
+        if [ -n "$searching" ]; then
+            echo "bestmove $searching"
+            searching=""
+        fi
+        ;;
+    quit)
+        exit 0
+        ;;
+    esac
+done